@@ -0,0 +1,107 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// First byte of the replies to the messages bspwm failed to process
+const FAILURE_MESSAGE: u8 = 7;
+
+/// How long to wait for bspwm to read a message and reply, the daemon is blocked meanwhile
+/// but bspwm handles its messages right away
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Socket of the bspwm running on `display` (e.g. `:0.1`), `BSPWM_SOCKET` or derived from the
+/// display like bspc does
+pub fn socket_path(display: &str) -> PathBuf {
+    socket_path_from(env::var_os("BSPWM_SOCKET"), display)
+}
+
+fn socket_path_from(socket: Option<OsString>, display: &str) -> PathBuf {
+    if let Some(path) = socket {
+        return path.into();
+    }
+    let (host, display, screen) = parse_display(display);
+    PathBuf::from(format!("/tmp/bspwm{}_{}_{}-socket", host, display, screen))
+}
+
+/// Split `host:display.screen`
+fn parse_display(display: &str) -> (&str, u32, u32) {
+    let (host, rest) = display.rsplit_once(':').unwrap_or(("", display));
+    let (display, screen) = rest.split_once('.').unwrap_or((rest, "0"));
+    (
+        host,
+        display.parse().unwrap_or(0),
+        screen.parse().unwrap_or(0),
+    )
+}
+
+/// Send a message to bspwm as `bspc <args>` would, returns the reply or a `WouldBlock` error
+/// after [`REPLY_TIMEOUT`]
+pub fn send<P: AsRef<Path>>(path: P, args: &[String]) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    stream.set_write_timeout(Some(REPLY_TIMEOUT))?;
+
+    let mut message = Vec::new();
+    for arg in args {
+        message.extend_from_slice(arg.as_bytes());
+        message.push(0);
+    }
+    stream.write_all(&message)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    match reply.split_first() {
+        Some((&FAILURE_MESSAGE, error)) => Err(io::Error::other(
+            String::from_utf8_lossy(error).trim_end().to_string(),
+        )),
+        _ => Ok(String::from_utf8_lossy(&reply).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{os::unix::net::UnixListener, thread};
+
+    #[test]
+    fn display() {
+        assert_eq!(parse_display(":0"), ("", 0, 0));
+        assert_eq!(parse_display("host:1.2"), ("host", 1, 2));
+        assert_eq!(
+            socket_path_from(None, ":0.1"),
+            Path::new("/tmp/bspwm_0_1-socket")
+        );
+        let socket = Some(OsString::from("/run/bspwm"));
+        assert_eq!(socket_path_from(socket, ":0.1"), Path::new("/run/bspwm"));
+    }
+
+    #[test]
+    fn message() {
+        let path = env::temp_dir().join(format!("rhkd-bspwm-{}-socket", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            for reply in &[&b"1\n"[..], b"\x07node -f: No nodes found.\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).unwrap();
+                assert_eq!(message, b"node\0-f\0west\0");
+                stream.write_all(reply).unwrap();
+            }
+        });
+
+        let args = ["node", "-f", "west"].map(String::from);
+        assert_eq!(send(&path, &args).unwrap(), "1\n");
+        let error = send(&path, &args).unwrap_err();
+        assert_eq!(error.to_string(), "node -f: No nodes found.");
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
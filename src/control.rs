@@ -0,0 +1,160 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rhkd::Request;
+
+use mio::{event::Source, unix::SourceFd};
+
+/// Unix socket accepting builtin commands (`:pause`, `:reload`, ...), one per line
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on `path`, a socket left there by an instance that is no longer running is
+    /// replaced while anything else is an error
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                let error = format!("{} exists and is not a socket", path.display());
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, error));
+            }
+            match UnixStream::connect(&path) {
+                Ok(_) => {
+                    let error = format!("another instance is listening on {}", path.display());
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, error));
+                }
+                // stale socket of a previous instance
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    fs::remove_file(&path)?
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        info!("listening on {}", path.display());
+        Ok(Self { listener, path })
+    }
+
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join("rhkd.socket")
+    }
+
+    /// Accept the pending connections and hand their requests to `f`
+    pub fn accept<F: FnMut(Request)>(&mut self, mut f: F) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = Self::serve(stream, &mut f) {
+                        warn!("control connection failed: {}", err);
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    error!("unable to accept control connection: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn serve<F: FnMut(Request)>(stream: UnixStream, f: &mut F) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                // the client kept the connection open without sending anything
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            };
+            let cmd = line.trim();
+            if cmd.is_empty() {
+                continue;
+            }
+            match cmd.trim_start_matches(':').parse() {
+                Ok(request) => {
+                    debug!("control request: {:?}", request);
+                    f(request);
+                    writeln!(writer, "ok")?;
+                }
+                Err(_) => writeln!(writer, "error: unknown command {}", cmd)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source for ControlSocket {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.listener.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.listener.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        SourceFd(&self.listener.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{io::Read, net::Shutdown};
+
+    #[test]
+    fn serve() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client
+            .write_all(b":pause\n\n  mode resize \nspawn\nreload")
+            .unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut requests = Vec::new();
+        ControlSocket::serve(server, &mut |request| requests.push(request)).unwrap();
+        assert_eq!(
+            requests,
+            [
+                Request::Pause,
+                Request::Mode("resize".to_string()),
+                Request::Reload
+            ]
+        );
+        let mut replies = String::new();
+        client.read_to_string(&mut replies).unwrap();
+        assert_eq!(replies, "ok\nok\nerror: unknown command spawn\nok\n");
+    }
+}
@@ -1,29 +1,335 @@
 use std::{
     alloc::Layout,
+    collections::HashMap,
     convert::TryInto,
-    fs::OpenOptions,
+    fs::{self, OpenOptions},
     io::{self, BufWriter},
+    mem,
     path::Path,
+    process::ExitStatus,
     str::FromStr,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    exec::{Exec, IntoExec},
-    key::{self, Key, Locks},
-    keyboard::Keyboard,
+    ewmh::WindowState,
+    exec::ExecHandle,
+    exec::{Context, Exec, Flow, IntoExec},
+    key::{self, Aliases, Key, Locks},
+    keyboard::{DisplayContext, Event, EventKind, KeyEvent, Keyboard, Time},
+    options::{Options, Policy, Repeat},
+    process::{Capture, Process, Processes, CAPTURE_POLL},
+    selection::{Selection, CONVERT_TIMEOUT},
+    window::{WindowInfo, WindowMatch},
+    xtest::Typing,
 };
 
 use fst::{self, Map, MapBuilder};
 
+const KEY_SIZE: usize = Layout::new::<Key>().size();
+
+/// fst entries are the mode index followed by the key
+type Entry = [u8; KEY_SIZE + 1];
+
+fn entry(mode: u8, key: Key) -> Entry {
+    let mut entry = [0; KEY_SIZE + 1];
+    entry[0] = mode;
+    entry[1..].copy_from_slice(&<[u8; KEY_SIZE]>::from(key));
+    entry
+}
+
+/// Name of the mode in which the bindings are made outside of [`Builder::mode`]
+pub const DEFAULT_MODE: &str = "default";
+/// Name of the mode holding the escape hotkeys, only grabbed while paused
+pub const PAUSED_MODE: &str = "paused";
+
 pub struct Controler {
-    cmds: Box<[Exec]>,
+    cmds: Box<[Binding]>,
     map: Map<memmap::Mmap>,
+    /// window specific bindings, looked up before the fst
+    windows: HashMap<Entry, Vec<(WindowMatch, usize)>>,
+    window: Option<WindowInfo>,
+    grabs: Box<[Grab]>,
+    modes: Box<[String]>,
+    mode: u8,
+    paused: bool,
+    handle: Handle,
+    pending: Vec<Request>,
+    processes: Processes,
+    /// runs waiting for the previous one of their binding to exit, see [`Policy::Queue`]
+    queue: Vec<(usize, KeyEvent, EventKind)>,
+    /// sequences waiting for the child of their current step to exit
+    steps: Vec<Step>,
+    /// runs waiting for the selections used by their command, until [`CONVERT_TIMEOUT`]
+    converting: Vec<(Converting, Instant)>,
+    /// outputs still read after their child exited, see [`Exec::Capture`]
+    captures: Vec<Capture>,
+    /// texts being typed one after the other, see [`DisplayContext::type_next`]
+    typing: Vec<Typing>,
+    /// press waiting to be resolved as a tap, a hold or a double tap
+    tap: Option<Tap>,
+    /// hotkey held down with a release binding, cancelled by the press of any other key
+    release: Option<(Key, usize)>,
+    /// the keyboard is frozen until the next [`Keyboard::allow_events`]
+    frozen: bool,
+    /// keycode and screen of the last key event
+    keycode: u32,
+    screen: usize,
+    /// lock modifiers ignored in the key events, see [`Builder::alias`]
+    locks: Locks,
+    hold_delay: Duration,
+    double_delay: Duration,
+}
+
+/// Run waiting for selections, see [`DisplayContext::convert_selection`]
+#[derive(Debug)]
+enum Converting {
+    Run(usize, KeyEvent, EventKind),
+    Step(Step),
+    Swap,
+}
+
+/// Sequence being run for a binding, advanced when the child of its current step exits
+#[derive(Debug)]
+struct Step {
+    cmd: usize,
+    event: KeyEvent,
+    kind: EventKind,
+    /// index of the next step to run
+    next: usize,
+    pid: u32,
+}
+
+/// Outcome of running a step of a sequence, see [`Step::advance`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Stepped {
+    /// the child spawned for the step
+    Child(u32),
+    /// ran in process
+    Done,
+    Failed,
+    /// the selections used by the step are being converted, it is run again once they are
+    Converting,
+}
+
+/// Where a sequence stopped, see [`Step::advance`]
+#[derive(Debug)]
+enum Advanced {
+    /// waiting for the child of its current step
+    Waiting(Step),
+    /// waiting for the selections of its next step
+    Converting(Step),
+    Finished,
+}
+
+impl Step {
+    /// Run the next steps with `run` until one has to be waited for
+    fn advance<F>(mut self, flow: Flow, len: usize, mut run: F) -> Advanced
+    where
+        F: FnMut(&Step) -> Stepped,
+    {
+        while self.next < len {
+            let stepped = run(&self);
+            if stepped == Stepped::Converting {
+                return Advanced::Converting(self);
+            }
+            self.next += 1;
+            match stepped {
+                Stepped::Child(pid) if flow != Flow::Parallel => {
+                    self.pid = pid;
+                    return Advanced::Waiting(self);
+                }
+                Stepped::Failed if flow == Flow::StopOnFailure => return Advanced::Finished,
+                _ => {}
+            }
+        }
+        Advanced::Finished
+    }
+}
+
+/// Tap, hold and double tap bindings of a pressed hotkey, see [`key::TAP`]
+#[derive(Debug, Clone, Copy)]
+struct Tap {
+    key: Key,
+    time: Time,
+    tap: Option<usize>,
+    hold: Option<usize>,
+    double: Option<usize>,
+    state: TapState,
+    deadline: Option<Instant>,
+}
+
+impl Tap {
+    /// The hotkey of the binding selected by `flag`
+    fn key(&self, flag: u32) -> Key {
+        self.key.merge(Key::mask(flag))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TapState {
+    /// waiting for the release or the hold delay
+    Pressed,
+    /// the hold binding ran, waiting for the release
+    Held,
+    /// waiting for a second press or the double tap delay
+    Released,
+}
+
+/// Action of a binding and its settings
+#[derive(Debug)]
+struct Binding {
+    exec: Exec,
+    options: Options,
+    /// time of the last run, see [`Repeat::Interval`]
+    fired: Option<Time>,
+}
+
+impl Binding {
+    /// Whether a press runs the binding according to its [`Repeat`], the time of the runs is
+    /// kept for [`Repeat::Interval`]
+    fn fires(&mut self, event: &KeyEvent) -> bool {
+        if event.repeat {
+            let allowed = match self.options.repeat.unwrap_or(Repeat::Always) {
+                Repeat::Once => false,
+                Repeat::Always => true,
+                Repeat::Interval(interval) => self.fired.is_none_or(|fired| {
+                    u128::from(event.time.saturating_sub(fired)) >= interval.as_millis()
+                }),
+            };
+            if !allowed {
+                return false;
+            }
+        }
+        self.fired = Some(event.time);
+        true
+    }
+
+    /// Policy applying to a new run while the children `running` it spawned are still running,
+    /// [`Policy::Allow`] when there are none
+    fn policy(&self, running: &[u32]) -> Policy {
+        match running.is_empty() {
+            true => Policy::Allow,
+            false => self.options.policy.unwrap_or(Policy::Allow),
+        }
+    }
+}
+
+/// Key grabbed for a binding, every binding has a grab for each lock combination
+#[derive(Debug, Clone, Copy)]
+struct Grab {
+    mode: u8,
+    key: Key,
+    cmd: usize,
+    /// grabbed synchronously to be able to replay the key
+    sync: bool,
+}
+
+/// Request made to the daemon, either by an action or by the builtin commands (`:reload`,
+/// `:mode resize`, ...)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Request {
+    /// Rebuild the bindings
+    Reload,
+    /// Stop the daemon
+    Quit,
+    /// Switch to the bindings of a mode
+    Mode(String),
+    /// Go back to the default mode
+    Abort,
+    /// Release the grabs of every binding
+    Pause,
+    /// Grab the bindings again after a pause
+    Resume,
+    /// Pause or resume
+    ToggleGrabs,
+    /// Switch to a desktop
+    Desktop(u32),
+    /// Close the focused window
+    Close,
+    /// Toggle a state of the focused window
+    Toggle(WindowState),
+    /// Move the focused window to a desktop
+    MoveToDesktop(u32),
+    /// Own a selection with the given text
+    SetSelection(Selection, String),
+    /// Exchange the primary selection and the clipboard
+    SwapSelections,
+}
+
+impl FromStr for Request {
+    type Err = ();
+
+    /// Parse a builtin command without its leading `:`
+    fn from_str(cmd: &str) -> Result<Self, Self::Err> {
+        let (name, text) = cmd.split_once(' ').unwrap_or((cmd, ""));
+        match name {
+            "set-clipboard" => return Ok(Self::SetSelection(Selection::Clipboard, text.into())),
+            "set-primary" => return Ok(Self::SetSelection(Selection::Primary, text.into())),
+            _ => {}
+        }
+        let mut args = cmd.split_whitespace();
+        let request = match (args.next().ok_or(())?, args.next()) {
+            ("reload", None) => Self::Reload,
+            ("quit", None) => Self::Quit,
+            ("mode", Some(mode)) => Self::Mode(mode.to_string()),
+            ("abort", None) => Self::Abort,
+            ("pause", None) => Self::Pause,
+            ("resume", None) => Self::Resume,
+            ("toggle-grabs", None) => Self::ToggleGrabs,
+            ("desktop", Some(desktop)) => Self::Desktop(desktop.parse().map_err(drop)?),
+            ("close", None) => Self::Close,
+            ("swap-selections", None) => Self::SwapSelections,
+            ("toggle", Some(state)) => Self::Toggle(state.parse()?),
+            ("move-to-desktop", Some(desktop)) => {
+                Self::MoveToDesktop(desktop.parse().map_err(drop)?)
+            }
+            _ => return Err(()),
+        };
+        match args.next() {
+            Some(_) => Err(()),
+            None => Ok(request),
+        }
+    }
+}
+
+/// Handle to the daemon given to actions through their [`Context`]
+#[derive(Debug, Default)]
+pub struct Handle {
+    requests: Vec<Request>,
+}
+
+impl Handle {
+    pub fn request(&mut self, request: Request) {
+        debug!("request: {:?}", request);
+        self.requests.push(request);
+    }
+    pub fn reload(&mut self) {
+        self.request(Request::Reload)
+    }
+    pub fn quit(&mut self) {
+        self.request(Request::Quit)
+    }
+    pub fn mode(&mut self, mode: &str) {
+        self.request(Request::Mode(mode.to_string()))
+    }
 }
 
 pub struct Builder<'a, 'kb> {
-    commands: Vec<Exec>,
-    binds: Vec<([u8; Layout::new::<Key>().size()], u64)>,
+    commands: Vec<Binding>,
+    defaults: Options,
+    binds: Vec<(Entry, u64)>,
+    windows: HashMap<Entry, Vec<(WindowMatch, usize)>>,
+    grabs: Vec<Grab>,
+    modes: Vec<String>,
+    mode: u8,
+    window: Option<WindowMatch>,
+    aliases: Aliases,
     locks: Locks,
+    hold_delay: Duration,
+    double_delay: Duration,
+    /// bindings [`Builder::bind_with`] was unable to make, [`Builder::finish`] fails if any
+    failed: usize,
     keyboard: &'a mut Keyboard<'kb>,
 }
 
@@ -31,40 +337,178 @@ impl<'a, 'kb> Builder<'a, 'kb> {
     pub fn new(keyboard: &'a mut Keyboard<'kb>) -> Self {
         Self {
             commands: Vec::new(),
+            defaults: Options::default(),
             binds: Vec::new(),
-            locks: Locks::new(),
+            windows: HashMap::new(),
+            grabs: Vec::new(),
+            modes: vec![DEFAULT_MODE.to_string()],
+            mode: 0,
+            window: None,
+            aliases: Aliases::default(),
+            locks: Locks::new(&Aliases::default()),
+            hold_delay: Duration::from_millis(400),
+            double_delay: Duration::from_millis(250),
+            failed: 0,
             keyboard,
         }
     }
+    /// How long a hotkey must be held down to run its `hold` binding (default 400ms)
+    pub fn hold_delay(&mut self, delay: Duration) {
+        self.hold_delay = delay;
+    }
+    /// How long to wait for the second press of a `double` binding (default 250ms)
+    pub fn double_delay(&mut self, delay: Duration) {
+        self.double_delay = delay;
+    }
+    /// Name `modifier` (`mod1` to `mod5`) `alias` in the hotkeys of the bindings made afterwards,
+    /// as listed by `xmodmap`, e.g. `b.alias("mod3", "hyper")`. The defaults are in
+    /// [`key::xmodmap`].
+    pub fn alias(&mut self, modifier: &str, alias: &str) {
+        if self.aliases.set(modifier, alias).is_err() {
+            error!("unable to alias {}, expected mod1 to mod5", modifier);
+            self.failed += 1;
+        }
+        self.locks = Locks::new(&self.aliases);
+    }
+    /// Settings of the bindings made afterwards that don't override them
+    pub fn defaults(&mut self, options: Options) {
+        self.defaults = options;
+    }
     pub fn bind<T: IntoExec>(&mut self, pattern: &str, cmd: T) {
-        self.try_bind(pattern, cmd).expect("Unable to bind key");
+        self.bind_with(pattern, cmd, Options::default());
+    }
+    /// Bind a hotkey, the errors are logged and make [`Builder::finish`] fail
+    pub fn bind_with<T: IntoExec>(&mut self, pattern: &str, cmd: T, options: Options) {
+        if self.try_bind_with(pattern, cmd, options).is_err() {
+            error!("unable to bind {}", pattern);
+            self.failed += 1;
+        }
     }
+    #[allow(clippy::result_unit_err)]
     pub fn try_bind<T: IntoExec>(&mut self, pattern: &str, cmd: T) -> Result<(), key::Error> {
-        info!("mapping: {} -> {:?}", pattern, cmd);
-        let key = Key::from_str(pattern)?;
-        let cmd = cmd.into_exec()?;
+        self.try_bind_with(pattern, cmd, Options::default())
+    }
+    #[allow(clippy::result_unit_err)]
+    pub fn try_bind_with<T: IntoExec>(
+        &mut self,
+        pattern: &str,
+        cmd: T,
+        options: Options,
+    ) -> Result<(), key::Error> {
+        let key = Key::parse(pattern, &self.aliases)?;
+        let exec = cmd.into_exec()?;
+        let options = options.merge(&self.defaults);
+        if let Some(conflict) = options.detach_conflict() {
+            error!(
+                "unable to bind {}, detach can't be combined with {}",
+                pattern, conflict
+            );
+            return Err(());
+        }
+        info!("mapping: {} -> {:?} {:?}", pattern, exec, options);
         let Locks { num, caps } = self.locks;
 
         let numlocked = key.merge(Key::mask(num.unwrap_or(0)));
         let capslocked = key.merge(Key::mask(caps.unwrap_or(x11::xlib::LockMask)));
         let all_locked = numlocked.merge(capslocked);
 
-        self.keyboard.grab_key(key).map_err(|_| ())?;
-        self.keyboard.grab_key(numlocked).map_err(|_| ())?;
-        self.keyboard.grab_key(capslocked).map_err(|_| ())?;
-        self.keyboard.grab_key(all_locked).map_err(|_| ())?;
-
-        let idx = self.commands.len() as u64;
-        self.commands.push(cmd);
-        self.binds.push((key.into(), idx));
-        self.binds.push((numlocked.into(), idx));
-        self.binds.push((capslocked.into(), idx));
-        self.binds.push((all_locked.into(), idx));
+        // keys with window specific bindings are replayed when no binding matches, the next
+        // key events after a release binding's key are reported to know if it was pressed alone
+        let sync = self.window.is_some()
+            || key.flags() & key::RELEASE != 0
+            || self
+                .grabs
+                .iter()
+                .any(|grab| grab.sync && grab.mode == self.mode && grab.key == key.without_flags());
+        let keys = [key, numlocked, capslocked, all_locked];
+
+        // bindings of the other modes are grabbed when entering the mode
+        if self.mode == 0 {
+            for key in keys.iter().map(|key| key.without_flags()) {
+                match sync {
+                    true => self.keyboard.grab_key_sync(key),
+                    false => self.keyboard.grab_key(key),
+                }
+                .map_err(|_| ())?;
+            }
+        }
+
+        let idx = self.commands.len();
+        self.commands.push(Binding {
+            exec,
+            options,
+            fired: None,
+        });
+        for &key in &keys {
+            match self.window {
+                Some(ref window) => self
+                    .windows
+                    .entry(entry(self.mode, key))
+                    .or_default()
+                    .push((window.clone(), idx)),
+                None => self.binds.push((entry(self.mode, key), idx as u64)),
+            }
+            self.grabs.push(Grab {
+                mode: self.mode,
+                key: key.without_flags(),
+                cmd: idx,
+                sync,
+            });
+        }
+        if sync {
+            // the grabs of the previous bindings of this key are now synchronous too
+            let mode = self.mode;
+            let keys = keys.map(Key::without_flags);
+            self.grabs
+                .iter_mut()
+                .filter(|grab| grab.mode == mode && keys.contains(&grab.key))
+                .for_each(|grab| grab.sync = true);
+        }
         Ok(())
     }
 
+    /// Make the bindings of `f` only when the focused window matches `window`, other windows
+    /// fall back to the regular bindings of the key or receive the key itself.
+    pub fn window<F: FnOnce(&mut Self)>(&mut self, window: WindowMatch, f: F) {
+        let previous = self.window.replace(window);
+        f(self);
+        self.window = previous;
+    }
+
+    /// Hotkey grabbed only while paused to resume, see [`Request::Pause`]
+    pub fn escape(&mut self, pattern: &str) {
+        self.mode(PAUSED_MODE, |b| {
+            b.bind(pattern, Exec::Builtin(Request::Resume))
+        });
+    }
+
+    /// Make the bindings of `f` in the mode `name`, they are only active once switched to with
+    /// `:mode <name>` and until `:abort`.
+    pub fn mode<F: FnOnce(&mut Self)>(&mut self, name: &str, f: F) {
+        let mode = match self.modes.iter().position(|mode| mode == name) {
+            Some(mode) => mode,
+            None => {
+                self.modes.push(name.to_string());
+                self.modes.len() - 1
+            }
+        };
+        let mode = mode.try_into().expect("too many modes");
+        let previous = mem::replace(&mut self.mode, mode);
+        f(self);
+        self.mode = previous;
+    }
+
+    /// Build the fst at `path`, it is written next to it first and then renamed so that the
+    /// fst of a running controler is never modified
     pub fn finish<T: AsRef<Path>>(mut self, path: T) -> io::Result<Controler> {
+        if self.failed > 0 {
+            let error = format!("unable to make {} bindings", self.failed);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
         info!("started building fst");
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
         self.commands.shrink_to_fit();
         self.binds.sort_unstable_by_key(|k| k.0);
 
@@ -75,7 +519,7 @@ impl<'a, 'kb> Builder<'a, 'kb> {
             .write(true)
             .create(true)
             .truncate(true)
-            .open(path)?;
+            .open(&tmp)?;
 
         let mut b = MapBuilder::new(BufWriter::new(file)).map_err(fsterror_to_io)?;
         self.binds
@@ -87,42 +531,935 @@ impl<'a, 'kb> Builder<'a, 'kb> {
             .map_err(fsterror_to_io)?
             .into_inner()
             .expect("issue with the inner bufwriter");
+        fs::rename(&tmp, path)?;
 
         let map = Map::new(unsafe { memmap::Mmap::map(&file)? }).map_err(fsterror_to_io)?;
         info!("finished building fst");
-        Ok(Controler { cmds, map })
+        Ok(Controler {
+            cmds,
+            map,
+            windows: self.windows,
+            window: self.keyboard.active_window(),
+            grabs: self.grabs.into_boxed_slice(),
+            modes: self.modes.into_boxed_slice(),
+            mode: 0,
+            paused: false,
+            handle: Handle::default(),
+            pending: Vec::new(),
+            processes: Processes::default(),
+            queue: Vec::new(),
+            steps: Vec::new(),
+            converting: Vec::new(),
+            captures: Vec::new(),
+            typing: Vec::new(),
+            tap: None,
+            release: None,
+            frozen: false,
+            keycode: 0,
+            screen: 0,
+            locks: self.locks,
+            hold_delay: self.hold_delay,
+            double_delay: self.double_delay,
+        })
     }
 }
 
 impl Controler {
-    pub fn execute(&mut self, key: Key) {
-        if let Some(index) = self
-            .map
-            .get::<[u8; Layout::new::<Key>().size()]>(key.into())
+    pub fn execute(&mut self, keyboard: &mut Keyboard, event: &Event) {
+        let event = match event {
+            Event::KeyPress(event) => event,
+            Event::KeyRelease(event) => return self.release(keyboard, event),
+            Event::ActiveWindow => {
+                self.window = keyboard.active_window();
+                debug!("active window: {:?}", self.window);
+                return;
+            }
+            Event::Selection => {
+                self.resume_converted(keyboard);
+                return;
+            }
+            Event::Other => return,
+        };
+        let key = event.key;
+        self.keycode = event.keycode;
+        self.screen = event.screen;
+        if keyboard.context().screen() != event.screen {
+            keyboard.context().set_screen(event.screen);
+            self.window = keyboard.active_window();
+            debug!(
+                "screen: {} | active window: {:?}",
+                event.screen, self.window
+            );
+        }
+        let index = self.lookup(key);
+        let timed = self.tap_press(keyboard, event);
+
+        // a press of another key cancels the release binding
+        if self
+            .release
+            .is_some_and(|(held, _)| { held.sym } != { key.sym })
         {
-            if usize::max_value()
-                .try_into()
-                .map(|v| index > v)
-                .unwrap_or(true)
+            self.release = None;
+        }
+        let release = self.lookup(key.merge(Key::mask(key::RELEASE)));
+        if let (Some(release), false) = (release, event.repeat) {
+            self.release = Some((
+                key.without_locks(self.locks).merge(Key::mask(key::RELEASE)),
+                release,
+            ));
+        }
+
+        let frozen = mem::take(&mut self.frozen);
+        if frozen || self.grabs.iter().any(|grab| grab.sync && grab.key == key) {
+            match self.release {
+                Some(_) => self.allow_next_event(keyboard, event.time),
+                None => keyboard.allow_events(index.is_none() && !timed, event.time),
+            }
+        }
+        if let Some(index) = index {
+            let event = KeyEvent {
+                key: key.without_locks(self.locks),
+                ..*event
+            };
+            self.run(keyboard, index, event, EventKind::Press);
+            self.process_requests(keyboard);
+        } else if !timed && release.is_none() {
+            warn!("unmatched combination {:?}", key);
+        }
+    }
+
+    fn release(&mut self, keyboard: &mut Keyboard, event: &KeyEvent) {
+        let released = match self.release {
+            Some((held, index)) if { held.sym } == { event.key.sym } => Some((held, index)),
+            _ => None,
+        };
+        if released.is_some() {
+            self.release = None;
+        }
+        if mem::take(&mut self.frozen) {
+            match self.release {
+                Some(_) => self.allow_next_event(keyboard, event.time),
+                None => keyboard.allow_events(false, event.time),
+            }
+        }
+        if let Some((key, index)) = released {
+            self.fire(keyboard, index, key, event.time);
+        }
+        self.tap_release(keyboard, event);
+    }
+
+    fn allow_next_event(&mut self, keyboard: &mut Keyboard, time: Time) {
+        keyboard.allow_next_event(time);
+        self.frozen = true;
+    }
+
+    /// Track the press of a hotkey with tap, hold or double tap bindings, returns whether it
+    /// has any
+    fn tap_press(&mut self, keyboard: &mut Keyboard, event: &KeyEvent) -> bool {
+        let key = event.key;
+        match self.tap {
+            Some(tap) if tap.key == key.without_locks(self.locks) => {
+                if event.repeat {
+                    return true;
+                }
+                if tap.state == TapState::Released {
+                    self.tap = None;
+                    if let Some(double) = tap.double {
+                        self.fire(keyboard, double, tap.key(key::DOUBLE), event.time);
+                        return true;
+                    }
+                }
+            }
+            // another key interrupts a tap waiting for a double tap
+            Some(tap) => {
+                self.tap = None;
+                if let (TapState::Released, Some(index)) = (tap.state, tap.tap) {
+                    self.fire(keyboard, index, tap.key(key::TAP), tap.time);
+                }
+            }
+            None => {}
+        }
+        let flag = |c: &Self, flag| c.lookup(key.merge(Key::mask(flag)));
+        let (tap, hold, double) = (
+            flag(self, key::TAP),
+            flag(self, key::HOLD),
+            flag(self, key::DOUBLE),
+        );
+        if tap.is_none() && hold.is_none() && double.is_none() {
+            return false;
+        }
+        self.tap = Some(Tap {
+            key: key.without_locks(self.locks),
+            time: event.time,
+            tap,
+            hold,
+            double,
+            state: TapState::Pressed,
+            deadline: hold.map(|_| Instant::now() + self.hold_delay),
+        });
+        true
+    }
+
+    fn tap_release(&mut self, keyboard: &mut Keyboard, event: &KeyEvent) {
+        let mut tap = match self.tap {
+            // the modifiers may be released first, only the keysym is compared
+            Some(tap) if { tap.key.sym } == { event.key.sym } => tap,
+            _ => return,
+        };
+        self.tap = None;
+        if tap.state != TapState::Pressed {
+            return;
+        }
+        let held = u128::from(event.time.saturating_sub(tap.time));
+        match (tap.hold, tap.double, tap.tap) {
+            (Some(hold), _, _) if held >= self.hold_delay.as_millis() => {
+                self.fire(keyboard, hold, tap.key(key::HOLD), tap.time);
+            }
+            (_, Some(_), _) => {
+                tap.state = TapState::Released;
+                tap.deadline = Some(Instant::now() + self.double_delay);
+                self.tap = Some(tap);
+            }
+            (_, None, Some(index)) => {
+                self.fire(keyboard, index, tap.key(key::TAP), event.time);
+            }
+            _ => {}
+        }
+    }
+
+    /// Time left before the pending hold or double tap delay, the next command timeout or the
+    /// next key typed expires, to be used as the poll timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        let tap = self.tap.as_ref().and_then(|tap| tap.deadline);
+        let converting = self.converting.iter().map(|&(_, deadline)| deadline);
+        let captures = (!self.captures.is_empty()).then(|| Instant::now() + CAPTURE_POLL);
+        let deadline = tap
+            .into_iter()
+            .chain(self.processes.deadline())
+            .chain(converting)
+            .chain(captures)
+            .chain(self.typing.first().map(Typing::deadline))
+            .min()?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Run the hold and tap bindings whose delay expired, terminate the commands that timed
+    /// out and type the next key, to be called after each poll
+    pub fn tick(&mut self, keyboard: &mut Keyboard) {
+        self.processes.expire();
+        self.type_next(keyboard);
+        self.set_captured(keyboard);
+        self.resume_converted(keyboard);
+        let mut tap = match self.tap {
+            Some(tap)
+                if tap
+                    .deadline
+                    .is_some_and(|deadline| deadline <= Instant::now()) =>
             {
+                tap
+            }
+            _ => return,
+        };
+        self.tap = None;
+        match (tap.state, tap.hold, tap.tap) {
+            (TapState::Pressed, Some(hold), _) => {
+                tap.state = TapState::Held;
+                tap.deadline = None;
+                self.tap = Some(tap);
+                self.fire(keyboard, hold, tap.key(key::HOLD), tap.time);
+            }
+            (TapState::Released, _, Some(index)) => {
+                self.fire(keyboard, index, tap.key(key::TAP), tap.time);
+            }
+            _ => {}
+        }
+    }
+
+    /// Run a tap, hold, double tap or release binding of `key`
+    fn fire(&mut self, keyboard: &mut Keyboard, index: usize, key: Key, time: Time) {
+        let kind = match key.flags() {
+            key::TAP | key::RELEASE => EventKind::Release,
+            _ => EventKind::Press,
+        };
+        let event = KeyEvent {
+            key,
+            keycode: self.keycode,
+            time,
+            repeat: false,
+            screen: self.screen,
+        };
+        self.run(keyboard, index, event, kind);
+        self.process_requests(keyboard);
+    }
+
+    /// Run a binding according to its repeat and concurrency policies
+    fn run(&mut self, keyboard: &mut Keyboard, index: usize, event: KeyEvent, kind: EventKind) {
+        if !self.cmds[index].fires(&event) {
+            trace!("ignoring autorepeat | {}", event.key);
+            return;
+        }
+
+        let running = self.processes.running(index).map(Process::id);
+        let running = running.collect::<Vec<_>>();
+        match self.cmds[index].policy(&running) {
+            Policy::Allow => {}
+            Policy::Skip => {
+                info!("skipping {} | already running: {:?}", event.key, running);
+                return;
+            }
+            Policy::Restart => {
+                info!("restarting {} | killing: {:?}", event.key, running);
+                running.iter().for_each(|&pid| self.processes.kill(pid));
+                self.steps.retain(|step| step.cmd != index);
+            }
+            Policy::Queue => {
+                info!("queuing {} | running: {:?}", event.key, running);
+                self.queue.push((index, event, kind));
                 return;
             }
-            let t = &mut self.cmds[index as usize];
-            match t.spawn() {
-                Ok(mut handle) => {
-                    info!("spawned command | pid: {:?}", handle.id());
-                    let _ = handle.try_wait(); // try to avoid zombies if possible
+        }
+
+        match self.cmds[index].exec {
+            Exec::Sequence(..) => self.step(
+                keyboard,
+                Step {
+                    cmd: index,
+                    event,
+                    kind,
+                    next: 0,
+                    pid: 0,
+                },
+                false,
+            ),
+            _ => {
+                if self.convert(keyboard, index, None) {
+                    let deadline = Instant::now() + CONVERT_TIMEOUT;
+                    self.converting
+                        .push((Converting::Run(index, event, kind), deadline));
+                    return;
                 }
-                Err(err) => {
+                if let Err(err) = self.spawn(keyboard, index, None, event, kind) {
                     error!("unable to spawn command: {:?}", err);
                 }
             }
-        } else {
-            warn!("unmatched combination {:?}", key);
         }
     }
+
+    /// Ask for the selections used by the command of a binding, or of one of its steps, that
+    /// the daemon doesn't own, returns whether their conversion has to be waited for
+    fn convert(&mut self, keyboard: &mut Keyboard, index: usize, step: Option<usize>) -> bool {
+        let selections = match (&self.cmds[index].exec, step) {
+            (Exec::Sequence(_, steps), Some(step)) => steps[step].selections(),
+            (exec, _) => exec.selections(),
+        };
+        convert_selections(keyboard.context(), &selections)
+    }
+
+    /// Resume the runs whose selections were converted, or that waited for too long
+    fn resume_converted(&mut self, keyboard: &mut Keyboard) {
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.converting.len() {
+            let (ref waiting, deadline) = self.converting[i];
+            let selections = match *waiting {
+                Converting::Run(index, ..) => self.cmds[index].exec.selections(),
+                Converting::Step(ref step) => match self.cmds[step.cmd].exec {
+                    Exec::Sequence(_, ref steps) => steps[step.next].selections(),
+                    _ => Vec::new(),
+                },
+                Converting::Swap => vec![Selection::Primary, Selection::Clipboard],
+            };
+            let display = keyboard.context();
+            let converted = selections.iter().all(|&s| display.selection(s).is_some());
+            if !converted && deadline > now {
+                i += 1;
+                continue;
+            }
+            if !converted {
+                warn!("the selection owner did not answer | {:?}", selections);
+            }
+            match self.converting.remove(i).0 {
+                Converting::Run(index, event, kind) => {
+                    if let Err(err) = self.spawn(keyboard, index, None, event, kind) {
+                        error!("unable to spawn command: {:?}", err);
+                    }
+                }
+                Converting::Step(step) => self.step(keyboard, step, true),
+                Converting::Swap => keyboard.context().swap_selections(),
+            }
+            self.process_requests(keyboard);
+        }
+    }
+
+    /// Type the next key of the first text being typed once its delay expired
+    fn type_next(&mut self, keyboard: &mut Keyboard) {
+        let typing = match self.typing.first_mut() {
+            Some(typing) if typing.deadline() <= Instant::now() => typing,
+            _ => return,
+        };
+        match keyboard.context().type_next(typing) {
+            Ok(true) => {}
+            Ok(false) => drop(self.typing.remove(0)),
+            Err(err) => {
+                error!("unable to type text: {:?}", err);
+                self.typing.remove(0);
+            }
+        }
+    }
+
+    /// Set the selections of the captured outputs read to the end
+    fn set_captured(&mut self, keyboard: &mut Keyboard) {
+        let (done, reading) = mem::take(&mut self.captures)
+            .into_iter()
+            .partition::<Vec<_>, _>(Capture::is_done);
+        self.captures = reading;
+        for capture in done {
+            let selection = capture.selection;
+            keyboard
+                .context()
+                .set_selection(selection, capture.output());
+        }
+    }
+
+    /// Run the action of a binding, or one of its steps, returns the pid of the spawned child
+    fn spawn(
+        &mut self,
+        keyboard: &mut Keyboard,
+        index: usize,
+        step: Option<usize>,
+        event: KeyEvent,
+        kind: EventKind,
+    ) -> io::Result<Option<u32>> {
+        let Binding { exec, options, .. } = &mut self.cmds[index];
+        let exec = match (exec, step) {
+            (Exec::Sequence(_, steps), Some(step)) => &mut steps[step],
+            (exec, _) => exec,
+        };
+        let capture = match exec {
+            Exec::Capture(selection, _) => Some(*selection),
+            _ => None,
+        };
+        let mut ctx = Context {
+            key: event.key,
+            kind,
+            time: event.time,
+            mode: &self.modes[usize::from(self.mode)],
+            window: self.window.as_ref(),
+            screen: event.screen,
+            daemon: &mut self.handle,
+            display: keyboard.context(),
+        };
+        match exec.spawn(&mut ctx, options)? {
+            ExecHandle::Command(child) => {
+                let pid = child.id();
+                info!("spawned command | {} | pid: {}", ctx.key, pid);
+                let mut process = Process::new(index, ctx.key, child);
+                process.group = options.kill_group.unwrap_or(false);
+                process.deadline = options.timeout.map(|timeout| process.started + timeout);
+                if let Some(selection) = capture {
+                    process.capture_output(selection);
+                }
+                process.log_output();
+                self.processes.insert(process);
+                Ok(Some(pid))
+            }
+            ExecHandle::Func => {
+                debug!("ran in process | {}", ctx.key);
+                Ok(None)
+            }
+            ExecHandle::Typing(typing) => {
+                debug!("typing text | {}", ctx.key);
+                self.typing.push(typing);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Run the next steps of a sequence until one has to be waited for, `converted` when the
+    /// selections of the next step were just converted
+    fn step(&mut self, keyboard: &mut Keyboard, step: Step, mut converted: bool) {
+        let (flow, len) = match self.cmds[step.cmd].exec {
+            Exec::Sequence(flow, ref steps) => (flow, steps.len()),
+            _ => return,
+        };
+        let advanced = step.advance(flow, len, |step| {
+            if !mem::take(&mut converted) && self.convert(keyboard, step.cmd, Some(step.next)) {
+                return Stepped::Converting;
+            }
+            match self.spawn(keyboard, step.cmd, Some(step.next), step.event, step.kind) {
+                Ok(Some(pid)) => Stepped::Child(pid),
+                Ok(None) => Stepped::Done,
+                Err(err) => {
+                    error!(
+                        "unable to spawn step {} | {} | {:?}",
+                        step.next + 1,
+                        step.event.key,
+                        err
+                    );
+                    Stepped::Failed
+                }
+            }
+        });
+        match advanced {
+            Advanced::Waiting(step) => self.steps.push(step),
+            Advanced::Converting(step) => {
+                let deadline = Instant::now() + CONVERT_TIMEOUT;
+                self.converting.push((Converting::Step(step), deadline));
+            }
+            Advanced::Finished => {}
+        }
+    }
+
+    /// Handle the exited children reaped by [`crate::process::wait_children`] on `SIGCHLD`,
+    /// the ones spawned by other controlers are left in `exited`
+    pub fn reap(&mut self, keyboard: &mut Keyboard, exited: &mut Vec<(u32, ExitStatus)>) {
+        for (mut process, status) in self.processes.reap(exited) {
+            let log = match status.success() {
+                true => log::Level::Info,
+                false => log::Level::Warn,
+            };
+            log!(
+                log,
+                "command exited | {} | pid: {} | {} | runtime: {:?} | {:?}",
+                process.key,
+                process.id(),
+                status,
+                process.runtime(),
+                self.cmds[process.cmd].exec
+            );
+            if let Some(capture) = process.captured() {
+                match status.success() {
+                    true => self.captures.push(capture),
+                    false => warn!("not setting {:?} after the failure", capture.selection),
+                }
+            }
+            if let Some(pos) = self.steps.iter().position(|s| s.pid == process.id()) {
+                let step = self.steps.remove(pos);
+                let flow = match self.cmds[step.cmd].exec {
+                    Exec::Sequence(flow, _) => flow,
+                    _ => Flow::Sequential,
+                };
+                match flow.proceeds(status.success()) {
+                    true => self.step(keyboard, step, false),
+                    false => info!(
+                        "stopping sequence | {} | step {} failed",
+                        step.event.key, step.next
+                    ),
+                }
+            }
+            // start the next queued run once the previous one is done
+            if self.processes.running(process.cmd).next().is_none() {
+                if let Some(pos) = self.queue.iter().position(|q| q.0 == process.cmd) {
+                    let (index, event, kind) = self.queue.remove(pos);
+                    self.run(keyboard, index, event, kind);
+                }
+            }
+        }
+        self.set_captured(keyboard);
+    }
+
+    /// Take over a new connection after the previous one was lost, the keys of the current mode
+    /// are grabbed again
+    pub fn reconnect(&mut self, keyboard: &mut Keyboard) {
+        self.tap = None;
+        self.release = None;
+        self.frozen = false;
+        // the keycodes were those of the previous server
+        self.typing.clear();
+        self.window = keyboard.active_window();
+        self.grab(keyboard);
+    }
+
+    /// Grab the keys of the current mode, e.g. after a failed reload
+    pub fn grab(&self, keyboard: &mut Keyboard) {
+        for (key, sync) in self.grabbed() {
+            let _ = match sync {
+                true => keyboard.grab_key_sync(key),
+                false => keyboard.grab_key(key),
+            };
+        }
+    }
+
+    /// Take over the children, queued runs, sequences and texts being typed of the controler
+    /// replaced by a reload. The bindings they belong to are kept, without their hotkeys, until
+    /// they are done.
+    pub fn take_over(&mut self, previous: Controler) {
+        let mut previous_cmds = previous
+            .cmds
+            .into_vec()
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        let mut cmds = mem::take(&mut self.cmds).into_vec();
+        let mut moved = HashMap::new();
+        let mut keep = |cmd: &mut usize| {
+            *cmd = *moved.entry(*cmd).or_insert_with(|| {
+                cmds.extend(previous_cmds[*cmd].take());
+                cmds.len() - 1
+            });
+        };
+
+        let mut processes = previous.processes;
+        processes
+            .iter_mut()
+            .for_each(|process| keep(&mut process.cmd));
+        let mut queue = previous.queue;
+        queue.iter_mut().for_each(|(cmd, ..)| keep(cmd));
+        let mut steps = previous.steps;
+        steps.iter_mut().for_each(|step| keep(&mut step.cmd));
+        let mut converting = previous.converting;
+        for (waiting, _) in converting.iter_mut() {
+            match waiting {
+                Converting::Run(cmd, ..) | Converting::Step(Step { cmd, .. }) => keep(cmd),
+                Converting::Swap => {}
+            }
+        }
+
+        self.cmds = cmds.into_boxed_slice();
+        self.processes = processes;
+        self.queue = queue;
+        self.steps = steps;
+        self.converting = converting;
+        self.captures = previous.captures;
+        self.typing = previous.typing;
+    }
+
+    /// Make a request to the daemon from outside of an action
+    pub fn request(&mut self, keyboard: &mut Keyboard, request: Request) {
+        self.handle.request(request);
+        self.process_requests(keyboard);
+    }
+
+    /// One iteration of the event loop, once the connection is readable or [`Controler::timeout`]
+    /// expired: handle the children of `exited` spawned by this controler, the key events and
+    /// the expired delays until the queue of Xlib is empty, returns the [`Controler::requests`]
+    pub fn dispatch(
+        &mut self,
+        keyboard: &mut Keyboard,
+        exited: &mut Vec<(u32, ExitStatus)>,
+    ) -> Vec<Request> {
+        let mut events = Vec::new();
+        loop {
+            self.reap(keyboard, exited);
+            keyboard.read_events(&mut events);
+            events
+                .drain(..)
+                .for_each(|event| self.execute(keyboard, &event));
+            // hold and double tap delays, command timeouts
+            self.tick(keyboard);
+            if !keyboard.has_events() {
+                return self.requests().collect();
+            }
+        }
+    }
+
+    /// Drain the requests that are left to the caller, [`Request::Reload`] and
+    /// [`Request::Quit`], since the last call
+    pub fn requests(&mut self) -> impl Iterator<Item = Request> + '_ {
+        self.pending.drain(..)
+    }
+
+    pub fn mode(&self) -> &str {
+        &self.modes[usize::from(self.mode)]
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn lookup(&self, key: Key) -> Option<usize> {
+        let paused = self.paused_mode().filter(|_| self.paused);
+        paused.iter().chain(&[self.mode, 0]).find_map(|&mode| {
+            let entry = entry(mode, key);
+            self.lookup_window(&entry)
+                .or_else(|| self.map.get(entry).and_then(|index| index.try_into().ok()))
+        })
+    }
+
+    fn lookup_window(&self, entry: &Entry) -> Option<usize> {
+        let window = self.window.as_ref()?;
+        self.windows
+            .get(entry)?
+            .iter()
+            .find(|(matcher, _)| matcher.matches(window))
+            .map(|&(_, index)| index)
+    }
+
+    fn paused_mode(&self) -> Option<u8> {
+        self.modes
+            .iter()
+            .position(|mode| mode == PAUSED_MODE)
+            .map(|mode| mode as u8)
+    }
+
+    fn process_requests(&mut self, keyboard: &mut Keyboard) {
+        for request in mem::take(&mut self.handle.requests) {
+            match request {
+                Request::Mode(name) => match self.modes.iter().position(|mode| *mode == name) {
+                    Some(mode) => self.transition(keyboard, |c| c.mode = mode as u8),
+                    None => warn!("unknown mode {}", name),
+                },
+                Request::Abort => self.transition(keyboard, |c| c.mode = 0),
+                Request::Pause => self.transition(keyboard, |c| c.paused = true),
+                Request::Resume => self.transition(keyboard, |c| c.paused = false),
+                Request::ToggleGrabs => self.transition(keyboard, |c| c.paused = !c.paused),
+                Request::Desktop(desktop) => keyboard.context().set_current_desktop(desktop),
+                Request::SetSelection(selection, text) => {
+                    keyboard.context().set_selection(selection, text)
+                }
+                Request::SwapSelections => {
+                    let selections = [Selection::Primary, Selection::Clipboard];
+                    match convert_selections(keyboard.context(), &selections) {
+                        true => {
+                            let deadline = Instant::now() + CONVERT_TIMEOUT;
+                            self.converting.push((Converting::Swap, deadline));
+                        }
+                        false => keyboard.context().swap_selections(),
+                    }
+                }
+                Request::Close | Request::Toggle(_) | Request::MoveToDesktop(_) => {
+                    let window = match self.window {
+                        Some(ref window) => window.id,
+                        None => {
+                            warn!("no focused window for {:?}", request);
+                            continue;
+                        }
+                    };
+                    let display = keyboard.context();
+                    match request {
+                        Request::Close => display.close_window(window),
+                        Request::Toggle(state) => display.toggle_state(window, state),
+                        Request::MoveToDesktop(desktop) => display.move_to_desktop(window, desktop),
+                        _ => unreachable!(),
+                    }
+                }
+                request => self.pending.push(request),
+            }
+        }
+    }
+
+    /// Update the grabs to match the state change made by `f`
+    fn transition<F: FnOnce(&mut Self)>(&mut self, keyboard: &mut Keyboard, f: F) {
+        let before = self.grabbed();
+        f(self);
+        let after = self.grabbed();
+        info!("mode: {} | paused: {}", self.mode(), self.paused);
+
+        before
+            .iter()
+            .filter(|(key, _)| !after.iter().any(|(k, _)| k == key))
+            .for_each(|&(key, _)| keyboard.ungrab_key(key));
+        after
+            .iter()
+            .filter(|grab| !before.contains(grab))
+            .for_each(|&(key, sync)| {
+                let _ = match sync {
+                    true => keyboard.grab_key_sync(key),
+                    false => keyboard.grab_key(key),
+                };
+            });
+    }
+
+    /// Keys that should be grabbed in the current state and whether synchronously
+    fn grabbed(&self) -> Vec<(Key, bool)> {
+        let paused = self.paused_mode();
+        let mut keys = Vec::new();
+        for grab in self.grabs.iter() {
+            let escape = Some(grab.mode) == paused;
+            if escape && !self.paused {
+                continue;
+            }
+            if !escape && grab.mode != 0 && grab.mode != self.mode {
+                continue;
+            }
+            // keep the bindings able to resume when paused
+            let resumes = escape
+                || matches!(
+                    self.cmds[grab.cmd].exec,
+                    Exec::Builtin(Request::Resume) | Exec::Builtin(Request::ToggleGrabs)
+                );
+            if (!self.paused || resumes) && !keys.contains(&(grab.key, grab.sync)) {
+                keys.push((grab.key, grab.sync));
+            }
+        }
+        keys
+    }
+}
+
+/// Convert the selections not owned by the daemon, returns whether the conversion has to be
+/// waited for
+fn convert_selections(display: &mut DisplayContext, selections: &[Selection]) -> bool {
+    let selections = selections
+        .iter()
+        .copied()
+        .filter(|&selection| !display.owns_selection(selection))
+        .collect::<Vec<_>>();
+    selections
+        .iter()
+        .for_each(|&selection| display.convert_selection(selection));
+    !selections.iter().all(|&s| display.selection(s).is_some())
 }
 
 fn fsterror_to_io(err: fst::Error) -> io::Error {
     io::Error::new(io::ErrorKind::Interrupted, err)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn first_step() -> Step {
+        let event = KeyEvent {
+            key: Key::from_str("super + o").unwrap(),
+            keycode: 32,
+            time: 0,
+            repeat: false,
+            screen: 0,
+        };
+        Step {
+            cmd: 0,
+            event,
+            kind: EventKind::Press,
+            next: 0,
+            pid: 0,
+        }
+    }
+
+    /// Advance a sequence of 3 steps from `step`, the steps run are pushed to `ran`
+    fn advance(step: Step, flow: Flow, outcomes: [Stepped; 3], ran: &mut Vec<usize>) -> Advanced {
+        step.advance(flow, 3, |step| {
+            ran.push(step.next);
+            outcomes[step.next]
+        })
+    }
+
+    fn binding(options: Options) -> Binding {
+        Binding {
+            exec: "rofi -show drun".parse().unwrap(),
+            options,
+            fired: None,
+        }
+    }
+
+    #[test]
+    fn policy() {
+        for policy in [Policy::Allow, Policy::Skip, Policy::Restart, Policy::Queue] {
+            let binding = binding(Options::new().policy(policy));
+            assert_eq!(binding.policy(&[]), Policy::Allow);
+            assert_eq!(binding.policy(&[42]), policy);
+        }
+        assert_eq!(binding(Options::new()).policy(&[42]), Policy::Allow);
+    }
+
+    #[test]
+    fn repeat() {
+        let press = |time, repeat| KeyEvent {
+            time,
+            repeat,
+            ..first_step().event
+        };
+        let mut always = binding(Options::new());
+        assert!(always.fires(&press(0, false)));
+        assert!(always.fires(&press(30, true)));
+
+        let mut once = binding(Options::new().repeat(Repeat::Once));
+        assert!(once.fires(&press(0, false)));
+        assert!(!once.fires(&press(30, true)));
+        assert!(once.fires(&press(60, false)));
+
+        let interval = Repeat::Interval(Duration::from_millis(100));
+        let mut interval = binding(Options::new().repeat(interval));
+        assert!(interval.fires(&press(0, false)));
+        assert!(!interval.fires(&press(30, true)));
+        assert!(!interval.fires(&press(60, true)));
+        assert!(interval.fires(&press(100, true)));
+        assert!(!interval.fires(&press(130, true)));
+        // a new press is never limited
+        assert!(interval.fires(&press(150, false)));
+    }
+
+    #[test]
+    fn sequential() {
+        let outcomes = [Stepped::Child(10), Stepped::Done, Stepped::Child(12)];
+        let mut ran = Vec::new();
+        let step = match advance(first_step(), Flow::Sequential, outcomes, &mut ran) {
+            Advanced::Waiting(step) => step,
+            advanced => panic!("unexpected {:?}", advanced),
+        };
+        assert_eq!((step.next, step.pid), (1, 10));
+        // the in process step doesn't stop the sequence
+        let step = match advance(step, Flow::Sequential, outcomes, &mut ran) {
+            Advanced::Waiting(step) => step,
+            advanced => panic!("unexpected {:?}", advanced),
+        };
+        assert_eq!((step.next, step.pid), (3, 12));
+        assert!(matches!(
+            advance(step, Flow::Sequential, outcomes, &mut ran),
+            Advanced::Finished
+        ));
+        assert_eq!(ran, [0, 1, 2]);
+
+        let outcomes = [Stepped::Failed, Stepped::Failed, Stepped::Done];
+        let mut ran = Vec::new();
+        let advanced = advance(first_step(), Flow::Sequential, outcomes, &mut ran);
+        assert!(matches!(advanced, Advanced::Finished));
+        assert_eq!(ran, [0, 1, 2]);
+    }
+
+    #[test]
+    fn stop_on_failure() {
+        let outcomes = [Stepped::Done, Stepped::Failed, Stepped::Done];
+        let mut ran = Vec::new();
+        let advanced = advance(first_step(), Flow::StopOnFailure, outcomes, &mut ran);
+        assert!(matches!(advanced, Advanced::Finished));
+        assert_eq!(ran, [0, 1]);
+    }
+
+    #[test]
+    fn parallel() {
+        let outcomes = [Stepped::Child(10), Stepped::Failed, Stepped::Child(12)];
+        let mut ran = Vec::new();
+        let advanced = advance(first_step(), Flow::Parallel, outcomes, &mut ran);
+        assert!(matches!(advanced, Advanced::Finished));
+        assert_eq!(ran, [0, 1, 2]);
+    }
+
+    #[test]
+    fn step_converting() {
+        let outcomes = [Stepped::Done, Stepped::Converting, Stepped::Done];
+        let mut ran = Vec::new();
+        let step = match advance(first_step(), Flow::Sequential, outcomes, &mut ran) {
+            Advanced::Converting(step) => step,
+            advanced => panic!("unexpected {:?}", advanced),
+        };
+        // the step is run again once its selections are converted
+        assert_eq!(step.next, 1);
+        assert_eq!(ran, [0, 1]);
+    }
+
+    #[test]
+    fn parse_request() {
+        assert_eq!(Request::from_str("reload"), Ok(Request::Reload));
+        assert_eq!(
+            Request::from_str("mode resize"),
+            Ok(Request::Mode("resize".to_string()))
+        );
+        assert_eq!(Request::from_str("toggle-grabs"), Ok(Request::ToggleGrabs));
+        assert_eq!(
+            Request::from_str("toggle fullscreen"),
+            Ok(Request::Toggle(WindowState::Fullscreen))
+        );
+        assert_eq!(Request::from_str("desktop 2"), Ok(Request::Desktop(2)));
+        assert!(Request::from_str("desktop next").is_err());
+        assert_eq!(
+            Request::from_str("set-clipboard a b"),
+            Ok(Request::SetSelection(
+                Selection::Clipboard,
+                "a b".to_string()
+            ))
+        );
+        assert!(Request::from_str("mode").is_err());
+        assert!(Request::from_str("quit now").is_err());
+        assert!(Request::from_str("spawn").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::{os::raw::c_long, str::FromStr};
+
+use x11::xlib::{
+    ClientMessage, ClientMessageData, CurrentTime, SubstructureNotifyMask,
+    SubstructureRedirectMask, Window, XClientMessageEvent, XEvent, XFlush, XSendEvent,
+};
+
+use crate::keyboard::{intern, DisplayContext};
+
+/// Source indication of the client messages, as a pager would
+const SOURCE_PAGER: c_long = 2;
+const STATE_TOGGLE: c_long = 2;
+
+/// `_NET_WM_STATE` toggled by [`crate::Request::Toggle`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WindowState {
+    Fullscreen,
+    Above,
+    Sticky,
+}
+
+impl WindowState {
+    fn atom_name(self) -> &'static str {
+        match self {
+            Self::Fullscreen => "_NET_WM_STATE_FULLSCREEN",
+            Self::Above => "_NET_WM_STATE_ABOVE",
+            Self::Sticky => "_NET_WM_STATE_STICKY",
+        }
+    }
+}
+
+impl FromStr for WindowState {
+    type Err = ();
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "fullscreen" => Ok(Self::Fullscreen),
+            "above" => Ok(Self::Above),
+            "sticky" => Ok(Self::Sticky),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Requests to the window manager as described by the EWMH specification
+impl DisplayContext {
+    /// Switch to a desktop, `_NET_CURRENT_DESKTOP`
+    pub fn set_current_desktop(&mut self, desktop: u32) {
+        let root = self.root();
+        let data = [c_long::from(desktop), CurrentTime as c_long, 0, 0, 0];
+        self.client_message(root, "_NET_CURRENT_DESKTOP", data);
+    }
+
+    /// Close a window gracefully, `_NET_CLOSE_WINDOW`
+    pub fn close_window(&mut self, window: Window) {
+        let data = [CurrentTime as c_long, SOURCE_PAGER, 0, 0, 0];
+        self.client_message(window, "_NET_CLOSE_WINDOW", data);
+    }
+
+    /// Toggle a state of a window, `_NET_WM_STATE`
+    pub fn toggle_state(&mut self, window: Window, state: WindowState) {
+        let atom = unsafe { intern(self.display_mut(), state.atom_name()) };
+        let data = [STATE_TOGGLE, atom as c_long, 0, SOURCE_PAGER, 0];
+        self.client_message(window, "_NET_WM_STATE", data);
+    }
+
+    /// Move a window to a desktop, `_NET_WM_DESKTOP`
+    pub fn move_to_desktop(&mut self, window: Window, desktop: u32) {
+        let data = [c_long::from(desktop), SOURCE_PAGER, 0, 0, 0];
+        self.client_message(window, "_NET_WM_DESKTOP", data);
+    }
+
+    /// Send a client message about `window` to the window manager through the root window
+    fn client_message(&mut self, window: Window, kind: &str, data: [c_long; 5]) {
+        debug!(
+            "client message {} | window: {:#x} | {:?}",
+            kind, window, data
+        );
+        let root = self.root();
+        let display = self.display_mut();
+        let mut event = XEvent::from(XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: i32::from(true),
+            display,
+            window,
+            message_type: unsafe { intern(display, kind) },
+            format: 32,
+            data: ClientMessageData::from(data),
+        });
+        unsafe {
+            XSendEvent(
+                display,
+                root,
+                i32::from(false),
+                SubstructureRedirectMask | SubstructureNotifyMask,
+                &mut event,
+            );
+            XFlush(display);
+        }
+    }
+}
@@ -1,32 +1,204 @@
 use std::{
-    io,
+    fmt, io,
     process::{Child, Command, Stdio},
     str::FromStr,
 };
 
-type Error = ();
+use crate::{
+    bspwm,
+    controler::{Handle, Request},
+    i3,
+    key::Key,
+    keyboard::{DisplayContext, EventKind, Time},
+    options::Options,
+    selection::Selection,
+    template::Template,
+    window::WindowInfo,
+    xtest::Typing,
+};
+
+pub type Error = ();
 
-pub trait IntoExec: std::fmt::Debug {
+/// In-process action receiving the [`Context`] of the hotkey that fired
+pub type Handler = Box<dyn FnMut(&mut Context) -> io::Result<()>>;
+
+pub trait IntoExec {
+    #[allow(clippy::result_unit_err)]
     fn into_exec(self) -> Result<Exec, Error>;
 }
 
-#[derive(Debug)]
 pub enum Exec {
-    Command(Command),
+    /// Command built at each run, see [`Template`]
+    Command(Template),
+    /// Command whose stdout is put in a selection once it exited successfully
+    /// (`:clipboard-from date +%F`)
+    Capture(Selection, Template),
     Func(fn() -> io::Result<()>),
+    Handler(Handler),
+    /// Builtin command (`:reload`, `:mode resize`, ...) run by the daemon itself
+    Builtin(Request),
+    /// Message sent to bspwm's socket (`:bspc node -f west`), without spawning `bspc`
+    Bspwm(Vec<String>),
+    /// `RUN_COMMAND` message sent to i3 or sway's socket (`:i3 focus left`)
+    I3(String),
+    /// Hotkeys typed in the focused window (`:keys ctrl + Page_Up, ctrl + Tab`)
+    Keys(Vec<Key>),
+    /// UTF-8 text typed in the focused window (`:type hello`)
+    Text(String),
+    /// Steps run by the daemon according to the [`Flow`], see [`Sequence`]
+    Sequence(Flow, Vec<Exec>),
+}
+
+/// How the steps of a [`Sequence`] are run
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Flow {
+    /// One after the other
+    Sequential,
+    /// One after the other until a step fails
+    StopOnFailure,
+    /// All at once
+    Parallel,
+}
+
+impl Flow {
+    /// Whether the next step runs after the child of the previous one exited
+    pub fn proceeds(self, success: bool) -> bool {
+        success || self != Self::StopOnFailure
+    }
+}
+
+/// Action made of several steps, e.g. `Sequence::new(vec!["bspc wm -h off", "bspc node older
+/// -f", "bspc wm -h on"])`. The steps are run without blocking the daemon and can't be
+/// sequences themselves.
+#[derive(Debug, Clone)]
+pub struct Sequence<T> {
+    flow: Flow,
+    steps: Vec<T>,
+}
+
+impl<T> Sequence<T> {
+    pub fn new(steps: Vec<T>) -> Self {
+        Self::with_flow(Flow::Sequential, steps)
+    }
+    pub fn stop_on_failure(steps: Vec<T>) -> Self {
+        Self::with_flow(Flow::StopOnFailure, steps)
+    }
+    pub fn parallel(steps: Vec<T>) -> Self {
+        Self::with_flow(Flow::Parallel, steps)
+    }
+    pub fn with_flow(flow: Flow, steps: Vec<T>) -> Self {
+        Self { flow, steps }
+    }
+}
+
+/// What triggered an [`Exec`]
+pub struct Context<'a> {
+    /// Hotkey that fired, without the lock modifiers
+    pub key: Key,
+    pub kind: EventKind,
+    /// X server time of the event, in milliseconds
+    pub time: Time,
+    /// Mode the hotkey fired in
+    pub mode: &'a str,
+    /// Focused window when the hotkey fired
+    pub window: Option<&'a WindowInfo>,
+    /// Screen the hotkey fired on, see [`DisplayContext::screen_name`]
+    pub screen: usize,
+    /// Handle to the daemon running the action
+    pub daemon: &'a mut Handle,
+    /// Connection to the X server
+    pub display: &'a mut DisplayContext,
 }
 
 #[derive(Debug)]
 pub enum ExecHandle {
     Command(Child),
     Func,
+    /// Text typed by the caller with [`DisplayContext::type_next`]
+    Typing(Typing),
 }
 
 impl Exec {
-    pub fn spawn(&mut self) -> io::Result<ExecHandle> {
+    /// Selections used by the placeholders of the command, see [`Template::selections`]
+    pub fn selections(&self) -> Vec<Selection> {
+        match self {
+            Self::Command(template) | Self::Capture(_, template) => template.selections(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn spawn(&mut self, ctx: &mut Context, options: &Options) -> io::Result<ExecHandle> {
         match self {
-            Self::Command(command) => command.spawn().map(ExecHandle::Command),
+            Self::Command(template) => {
+                let mut command = template.command(ctx, options.shell.unwrap_or(false))?;
+                options.prepare(&mut command)?;
+                spawn_command(&mut command, ctx)
+            }
+            Self::Capture(_, template) => {
+                let mut command = template.command(ctx, options.shell.unwrap_or(false))?;
+                options.prepare(&mut command)?;
+                command.stdout(Stdio::piped());
+                spawn_command(&mut command, ctx)
+            }
             Self::Func(f) => f().map(|_| ExecHandle::Func),
+            Self::Handler(f) => f(ctx).map(|_| ExecHandle::Func),
+            Self::Builtin(request) => {
+                ctx.daemon.request(request.clone());
+                Ok(ExecHandle::Func)
+            }
+            Self::Bspwm(args) => {
+                let path = bspwm::socket_path(&ctx.display.screen_name(ctx.screen));
+                let reply = bspwm::send(path, args)?;
+                debug!("bspwm reply | {:?} | {}", args, reply.trim_end());
+                Ok(ExecHandle::Func)
+            }
+            Self::I3(command) => {
+                let reply = i3::run_command(i3::socket_path()?, command)?;
+                debug!("i3 reply | {} | {}", command, reply);
+                Ok(ExecHandle::Func)
+            }
+            Self::Keys(keys) => ctx.display.send_keys(keys).map(|_| ExecHandle::Func),
+            Self::Text(text) => ctx.display.type_text(text).map(ExecHandle::Typing),
+            Self::Sequence(..) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "sequences are run step by step by the controler",
+            )),
+        }
+    }
+}
+
+/// Spawn a prepared command with the context of the hotkey in its environment
+fn spawn_command(command: &mut Command, ctx: &mut Context) -> io::Result<ExecHandle> {
+    command
+        .env("RHKD_HOTKEY", ctx.key.to_string())
+        .env("RHKD_MODE", ctx.mode)
+        .env("DISPLAY", ctx.display.screen_name(ctx.screen));
+    match ctx.window {
+        Some(window) => command.env("RHKD_WINDOW_ID", window.id.to_string()),
+        None => command.env_remove("RHKD_WINDOW_ID"),
+    };
+    command.spawn().map(ExecHandle::Command)
+}
+
+impl fmt::Debug for Exec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Command(command) => f.debug_tuple("Command").field(command).finish(),
+            Self::Capture(selection, command) => f
+                .debug_tuple("Capture")
+                .field(selection)
+                .field(command)
+                .finish(),
+            Self::Func(func) => f.debug_tuple("Func").field(func).finish(),
+            Self::Handler(_) => f.write_str("Handler"),
+            Self::Builtin(request) => f.debug_tuple("Builtin").field(request).finish(),
+            Self::Bspwm(args) => f.debug_tuple("Bspwm").field(args).finish(),
+            Self::I3(command) => f.debug_tuple("I3").field(command).finish(),
+            Self::Keys(keys) => f.debug_tuple("Keys").field(keys).finish(),
+            Self::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            Self::Sequence(flow, steps) => {
+                f.debug_tuple("Sequence").field(flow).field(steps).finish()
+            }
         }
     }
 }
@@ -46,12 +218,47 @@ impl ExecHandle {
     }
 }
 
+impl IntoExec for Exec {
+    fn into_exec(self) -> Result<Exec, Error> {
+        Ok(self)
+    }
+}
+
 impl IntoExec for fn() -> io::Result<()> {
     fn into_exec(self) -> Result<Exec, Error> {
         Ok(Exec::Func(self))
     }
 }
 
+impl IntoExec for Handler {
+    fn into_exec(self) -> Result<Exec, Error> {
+        Ok(Exec::Handler(self))
+    }
+}
+
+impl<F> IntoExec for Box<F>
+where
+    F: FnMut(&mut Context) -> io::Result<()> + 'static,
+{
+    fn into_exec(self) -> Result<Exec, Error> {
+        Ok(Exec::Handler(self))
+    }
+}
+
+impl<T: IntoExec> IntoExec for Sequence<T> {
+    fn into_exec(self) -> Result<Exec, Error> {
+        let steps = self
+            .steps
+            .into_iter()
+            .map(|step| match step.into_exec()? {
+                Exec::Sequence(..) => Err(()),
+                exec => Ok(exec),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Exec::Sequence(self.flow, steps))
+    }
+}
+
 impl IntoExec for &str {
     fn into_exec(self) -> Result<Exec, Error> {
         FromStr::from_str(self)
@@ -61,12 +268,63 @@ impl IntoExec for &str {
 impl FromStr for Exec {
     type Err = Error;
     fn from_str(cmd: &str) -> Result<Self, Self::Err> {
-        let mut args = cmd.split(' ');
-        let mut bld: Command = Command::new(args.next().ok_or(())?);
-        bld.args(args);
-        bld.stdin(Stdio::null());
-        bld.stderr(Stdio::null());
-        bld.stdout(Stdio::null());
-        Ok(Self::Command(bld))
+        if let Some(builtin) = cmd.strip_prefix(':') {
+            if let Some(args) = builtin.strip_prefix("bspc ") {
+                return Ok(Self::Bspwm(
+                    args.split_whitespace().map(String::from).collect(),
+                ));
+            }
+            if let Some(command) = builtin.strip_prefix("i3 ") {
+                return Ok(Self::I3(command.trim().to_string()));
+            }
+            if let Some(keys) = builtin.strip_prefix("keys ") {
+                let keys = keys.split(',').map(Key::from_str);
+                return keys.collect::<Result<_, _>>().map(Self::Keys);
+            }
+            if let Some(text) = builtin.strip_prefix("type ") {
+                return Ok(Self::Text(text.to_string()));
+            }
+            if let Some(command) = builtin.strip_prefix("clipboard-from ") {
+                return match command.parse()? {
+                    Self::Command(template) => Ok(Self::Capture(Selection::Clipboard, template)),
+                    _ => Err(()),
+                };
+            }
+            return builtin.parse().map(Self::Builtin);
+        }
+        if cmd.trim().is_empty() {
+            return Err(());
+        }
+        Ok(Self::Command(Template::new(cmd)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_sequence() {
+        let steps = vec!["bspc wm -h off", "bspc node older -f"];
+        match Sequence::stop_on_failure(steps).into_exec() {
+            Ok(Exec::Sequence(Flow::StopOnFailure, steps)) => assert_eq!(steps.len(), 2),
+            exec => panic!("unexpected {:?}", exec),
+        }
+        let nested = Sequence::new(vec![
+            Sequence::new(vec!["true"]),
+            Sequence::new(vec!["false"]),
+        ]);
+        assert!(nested.into_exec().is_err());
+        let nested = Sequence::parallel(vec![Exec::Sequence(Flow::Sequential, Vec::new())]);
+        assert!(nested.into_exec().is_err());
+        assert!(Sequence::new(vec![":quit", ""]).into_exec().is_err());
+    }
+
+    #[test]
+    fn proceeds() {
+        assert!(Flow::Sequential.proceeds(false));
+        assert!(Flow::Parallel.proceeds(false));
+        assert!(Flow::StopOnFailure.proceeds(true));
+        assert!(!Flow::StopOnFailure.proceeds(false));
     }
 }
@@ -0,0 +1,126 @@
+use std::{
+    convert::TryInto,
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const MAGIC: &[u8] = b"i3-ipc";
+const RUN_COMMAND: u32 = 0;
+
+/// Socket of the running i3 or sway, `I3SOCK` or `SWAYSOCK`
+pub fn socket_path() -> io::Result<PathBuf> {
+    env::var_os("I3SOCK")
+        .or_else(|| env::var_os("SWAYSOCK"))
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "I3SOCK and SWAYSOCK are unset"))
+}
+
+/// Send a `RUN_COMMAND` message as `i3-msg <command>` would, returns the reply or the errors
+/// of the failed commands
+pub fn run_command<P: AsRef<Path>>(path: P, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    let len = command.len().try_into().map_err(io::Error::other)?;
+    let mut message = MAGIC.to_vec();
+    message.extend_from_slice(&u32::to_ne_bytes(len));
+    message.extend_from_slice(&RUN_COMMAND.to_ne_bytes());
+    message.extend_from_slice(command.as_bytes());
+    stream.write_all(&message)?;
+
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid reply"));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    let reply = String::from_utf8_lossy(&payload).into_owned();
+
+    let errors = errors(&reply);
+    match errors.is_empty() {
+        true => Ok(reply),
+        false => Err(io::Error::other(errors.join(", "))),
+    }
+}
+
+/// Errors of the results whose `success` is false, e.g. `[{"success":false,"error":"..."}]`
+fn errors(reply: &str) -> Vec<String> {
+    reply
+        .split("\"success\"")
+        .skip(1)
+        .filter(|result| !value(result).starts_with("true"))
+        .map(|result| match result.find("\"error\"") {
+            Some(pos) => string(value(&result[pos + 7..])),
+            None => "unknown error".to_string(),
+        })
+        .collect()
+}
+
+/// Value following a key
+fn value(s: &str) -> &str {
+    s.trim_start().trim_start_matches(':').trim_start()
+}
+
+/// Unescape the string at the start of `s`
+fn string(s: &str) -> String {
+    let mut chars = s.strip_prefix('"').unwrap_or(s).chars();
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => string.extend(chars.next()),
+            c => string.push(c),
+        }
+    }
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{os::unix::net::UnixListener, thread};
+
+    #[test]
+    fn reply() {
+        assert!(errors(r#"[{"success":true}]"#).is_empty());
+        assert_eq!(
+            errors(r#"[ { "success": false, "parse_error": true, "error": "Expected \"one\"" } ]"#),
+            vec![r#"Expected "one""#]
+        );
+    }
+
+    #[test]
+    fn message() {
+        let path = env::temp_dir().join(format!("rhkd-i3-{}-socket", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut message = [0; 14 + 10];
+            stream.read_exact(&mut message).unwrap();
+            assert_eq!(&message[..6], MAGIC);
+            assert_eq!(message[6..10], 10u32.to_ne_bytes());
+            assert_eq!(message[10..14], RUN_COMMAND.to_ne_bytes());
+            assert_eq!(&message[14..], b"focus left");
+
+            let payload = br#"[{"success":true}]"#;
+            let mut reply = MAGIC.to_vec();
+            reply.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+            reply.extend_from_slice(&RUN_COMMAND.to_ne_bytes());
+            reply.extend_from_slice(payload);
+            stream.write_all(&reply).unwrap();
+        });
+
+        assert_eq!(
+            run_command(&path, "focus left").unwrap(),
+            r#"[{"success":true}]"#
+        );
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
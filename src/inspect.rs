@@ -0,0 +1,84 @@
+use std::io;
+
+use rhkd::{Event, Key, KeyEvent, Keyboard};
+
+use mio::{Events, Interest, Poll, Token};
+use signal_hook::consts::signal::*;
+use signal_hook_mio::v0_7::Signals;
+
+const SIGNAL: Token = Token(0);
+const KEYBOARD: Token = Token(1);
+
+/// Grab the keyboard and print every key event in the binding syntax until a bare `Escape` is
+/// pressed.
+pub fn inspect(keyboard: &mut Keyboard) -> io::Result<()> {
+    let mut poll = Poll::new()?;
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    poll.registry()
+        .register(&mut signals, SIGNAL, Interest::READABLE)?;
+    poll.registry()
+        .register(keyboard, KEYBOARD, Interest::READABLE)?;
+
+    keyboard.grab_keyboard()?;
+    println!("inspecting key events, press Escape to quit");
+
+    let mut events = Events::with_capacity(32);
+    let mut xevents = Vec::with_capacity(32);
+    let res = 'outer: loop {
+        match poll.poll(&mut events, None) {
+            Ok(_) => {}
+            Err(a) if a.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => break Err(err),
+        }
+        for event in events.iter() {
+            match event.token() {
+                SIGNAL => break 'outer Ok(()),
+                KEYBOARD => {
+                    keyboard.read_events(&mut xevents);
+                    for event in xevents.drain(..) {
+                        match event {
+                            Event::KeyPress(event) => {
+                                let kind = match event.repeat {
+                                    true => "KeyPress (repeat)",
+                                    false => "KeyPress",
+                                };
+                                print_key(kind, event);
+                                if event.key.unlocked() == Key::sym(ESCAPE) {
+                                    break 'outer Ok(());
+                                }
+                            }
+                            Event::KeyRelease(event) => print_key("KeyRelease", event),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    };
+
+    keyboard.ungrab_keyboard();
+    poll.registry().deregister(keyboard)?;
+    res
+}
+
+const ESCAPE: u64 = x11::keysym::XK_Escape as u64;
+
+fn print_key(kind: &str, event: KeyEvent) {
+    let key = event.key;
+    let modifiers = key
+        .modifiers()
+        .map(|(name, alias)| format!("{} ({})", name, alias))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "{} keysym: {} ({:#x}) keycode: {} mask: {:#x} modifiers: [{}]",
+        kind,
+        key.sym_name(),
+        { key.sym },
+        event.keycode,
+        { key.mask },
+        modifiers,
+    );
+    println!("    hotkey: {}", key.unlocked());
+}
@@ -1,11 +1,34 @@
-use std::{alloc::Layout, ffi::CString, str::FromStr};
+use std::{
+    alloc::Layout,
+    ffi::{CStr, CString},
+    fmt,
+    str::FromStr,
+};
 
-use crate::binds::xmodmap;
-
-use x11::xlib::{self, XStringToKeysym};
+use x11::xlib::{self, XKeysymToString, XStringToKeysym};
 
 pub type Error = ();
 
+// default aliases for mod[1-5], see [`Aliases`]
+pub mod xmodmap {
+    pub const MOD1: &str = "alt";
+    pub const MOD2: &str = "Num_Lock";
+    pub const MOD3: &str = "_mod3";
+    pub const MOD4: &str = "super";
+    pub const MOD5: &str = "_mod5";
+}
+
+/// Pseudo modifier of the hotkeys run when the key is released before [`HOLD`] triggers
+pub const TAP: u32 = 1 << 24;
+/// Pseudo modifier of the hotkeys run when the key is held down for a while
+pub const HOLD: u32 = 1 << 25;
+/// Pseudo modifier of the hotkeys run when the key is tapped twice in a row
+pub const DOUBLE: u32 = 1 << 26;
+/// Pseudo modifier of the hotkeys run on release, written as an `@` before the keysym
+pub const RELEASE: u32 = 1 << 27;
+/// Pseudo modifiers, stored above the X11 modifier bits of the mask
+pub const FLAGS: u32 = TAP | HOLD | DOUBLE | RELEASE;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 #[repr(C, packed)]
 pub struct Key {
@@ -31,6 +54,85 @@ impl Key {
         self.mask |= other.mask;
         self
     }
+
+    pub const fn flags(&self) -> u32 {
+        self.mask & FLAGS
+    }
+
+    /// The key without its pseudo modifiers, as grabbed on the X server
+    pub const fn without_flags(mut self) -> Self {
+        self.mask &= !FLAGS;
+        self
+    }
+
+    /// Remove the num/caps lock bits from the mask, i.e. the key as it would be written in a
+    /// binding with the default [`Aliases`].
+    pub fn unlocked(self) -> Self {
+        self.without_locks(Locks::new(&Aliases::default()))
+    }
+
+    pub(crate) fn without_locks(mut self, locks: Locks) -> Self {
+        let Locks { num, caps } = locks;
+        self.mask &= !(num.unwrap_or(0) | caps.unwrap_or(xlib::LockMask));
+        self
+    }
+
+    /// Modifiers set in the mask as `(modN, alias)` pairs
+    pub fn modifiers(&self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        let mask = self.mask;
+        MODIFIERS
+            .iter()
+            .filter(move |(m, _, _)| mask & m != 0)
+            .map(|&(_, name, alias)| (name, alias))
+    }
+
+    /// Name of the keysym as understood by `XStringToKeysym`
+    pub fn sym_name(&self) -> String {
+        let sym = self.sym;
+        let name = unsafe { XKeysymToString(sym) };
+        if name.is_null() {
+            format!("{:#x}", sym)
+        } else {
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+const MODIFIERS: [(u32, &str, &str); 8] = [
+    (xlib::ShiftMask, "shift", "shift"),
+    (xlib::LockMask, "lock", "lock"),
+    (xlib::ControlMask, "control", "ctrl"),
+    (xlib::Mod1Mask, "mod1", xmodmap::MOD1),
+    (xlib::Mod2Mask, "mod2", xmodmap::MOD2),
+    (xlib::Mod3Mask, "mod3", xmodmap::MOD3),
+    (xlib::Mod4Mask, "mod4", xmodmap::MOD4),
+    (xlib::Mod5Mask, "mod5", xmodmap::MOD5),
+];
+
+/// Formats the key in the binding syntax, e.g. `super + shift + a`
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        for &(flag, name) in &[(TAP, "tap"), (HOLD, "hold"), (DOUBLE, "double")] {
+            if self.mask & flag != 0 {
+                parts.push(name.to_string());
+            }
+        }
+        if self.mask & xlib::AnyModifier != 0 {
+            parts.push("any".to_string());
+        }
+        for (name, alias) in self.modifiers() {
+            // placeholder aliases such as `_mod3` are not meant to be written
+            parts.push(if alias.starts_with('_') { name } else { alias }.to_string());
+        }
+        if self.sym != u64::MAX {
+            let release = if self.mask & RELEASE != 0 { "@" } else { "" };
+            parts.push(format!("{}{}", release, self.sym_name()));
+        }
+        write!(f, "{}", parts.join(" + "))
+    }
 }
 
 impl From<Key> for [u8; Layout::new::<Key>().size()] {
@@ -39,6 +141,49 @@ impl From<Key> for [u8; Layout::new::<Key>().size()] {
     }
 }
 
+/// Names of mod1 to mod5 in the hotkeys, as listed by `xmodmap`. The ones aliased as
+/// `Num_Lock` and `Caps_Lock` are ignored by the grabs, see [`crate::Builder::alias`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Aliases([String; 5]);
+
+impl Default for Aliases {
+    fn default() -> Self {
+        Self(
+            [
+                xmodmap::MOD1,
+                xmodmap::MOD2,
+                xmodmap::MOD3,
+                xmodmap::MOD4,
+                xmodmap::MOD5,
+            ]
+            .map(str::to_string),
+        )
+    }
+}
+
+impl Aliases {
+    /// Name `modifier` (`mod1` to `mod5`) `alias`
+    #[allow(clippy::result_unit_err)]
+    pub fn set(&mut self, modifier: &str, alias: &str) -> Result<(), Error> {
+        let index = match modifier {
+            "mod1" => 0,
+            "mod2" => 1,
+            "mod3" => 2,
+            "mod4" => 3,
+            "mod5" => 4,
+            _ => return Err(()),
+        };
+        self.0[index] = alias.to_string();
+        Ok(())
+    }
+
+    /// Mask of the modifier named `alias`
+    pub fn mask(&self, alias: &str) -> Option<u32> {
+        let index = self.0.iter().position(|name| name == alias)?;
+        Some(xlib::Mod1Mask << index)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct Locks {
     pub num: Option<u32>,
@@ -46,40 +191,34 @@ pub(crate) struct Locks {
 }
 
 impl Locks {
-    pub fn new() -> Self {
-        let num = match "Num_Lock" {
-            xmodmap::MOD1 => Some(xlib::Mod1Mask),
-            xmodmap::MOD2 => Some(xlib::Mod2Mask),
-            xmodmap::MOD3 => Some(xlib::Mod3Mask),
-            xmodmap::MOD4 => Some(xlib::Mod4Mask),
-            xmodmap::MOD5 => Some(xlib::Mod5Mask),
-            _ => None,
-        };
-        let caps = match "Caps_Lock" {
-            xmodmap::MOD1 => Some(xlib::Mod1Mask),
-            xmodmap::MOD2 => Some(xlib::Mod2Mask),
-            xmodmap::MOD3 => Some(xlib::Mod3Mask),
-            xmodmap::MOD4 => Some(xlib::Mod4Mask),
-            xmodmap::MOD5 => Some(xlib::Mod5Mask),
-            _ => None,
-        };
-        Self { num, caps }
+    pub fn new(aliases: &Aliases) -> Self {
+        Self {
+            num: aliases.mask("Num_Lock"),
+            caps: aliases.mask("Caps_Lock"),
+        }
     }
 }
 
 #[allow(unreachable_code)]
-fn parse_convert_modifier(k: &str) -> Result<Key, String> {
+fn parse_convert_modifier(k: &str, aliases: &Aliases) -> Result<Key, String> {
     match k {
         "any" => Ok(Key::mask(xlib::AnyModifier)),
+        "tap" => Ok(Key::mask(TAP)),
+        "hold" => Ok(Key::mask(HOLD)),
+        "double" => Ok(Key::mask(DOUBLE)),
         "shift" => Ok(Key::mask(xlib::ShiftMask)),
         "ctrl" | "control" => Ok(Key::mask(xlib::ControlMask)),
         "lock" => Ok(Key::mask(xlib::LockMask)),
-        "mod1" | xmodmap::MOD1 => Ok(Key::mask(xlib::Mod1Mask)),
-        "mod2" | xmodmap::MOD2 => Ok(Key::mask(xlib::Mod2Mask)),
-        "mod3" | xmodmap::MOD3 => Ok(Key::mask(xlib::Mod3Mask)),
-        "mod4" | xmodmap::MOD4 => Ok(Key::mask(xlib::Mod4Mask)),
-        "mod5" | xmodmap::MOD5 => Ok(Key::mask(xlib::Mod5Mask)),
-        sym => into_keysym(sym).map(Key::sym),
+        "mod1" => Ok(Key::mask(xlib::Mod1Mask)),
+        "mod2" => Ok(Key::mask(xlib::Mod2Mask)),
+        "mod3" => Ok(Key::mask(xlib::Mod3Mask)),
+        "mod4" => Ok(Key::mask(xlib::Mod4Mask)),
+        "mod5" => Ok(Key::mask(xlib::Mod5Mask)),
+        sym if sym.starts_with('@') => into_keysym(&sym[1..]).map(|sym| Key { sym, mask: RELEASE }),
+        name => match aliases.mask(name) {
+            Some(mask) => Ok(Key::mask(mask)),
+            None => into_keysym(name).map(Key::sym),
+        },
     }
 }
 
@@ -91,19 +230,27 @@ fn into_keysym(key: &str) -> Result<u64, String> {
     }
 }
 
-impl FromStr for Key {
-    type Err = Error;
-
-    fn from_str(input: &str) -> Result<Key, Self::Err> {
+impl Key {
+    /// Parse a hotkey with the given modifier aliases, [`Key::from_str`] uses the default ones
+    #[allow(clippy::result_unit_err)]
+    pub fn parse(input: &str, aliases: &Aliases) -> Result<Key, Error> {
         let mut key = Key::builder();
 
         for k in input.split('+') {
-            key = key.merge(parse_convert_modifier(k.trim()).map_err(|_| ())?);
+            key = key.merge(parse_convert_modifier(k.trim(), aliases).map_err(|_| ())?);
         }
         Ok(key)
     }
 }
 
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Key, Self::Err> {
+        Key::parse(input, &Aliases::default())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -111,7 +258,7 @@ mod test {
     #[test]
     fn parse() {
         assert_eq!(
-            parse_convert_modifier("ctrl").unwrap(),
+            parse_convert_modifier("ctrl", &Aliases::default()).unwrap(),
             Key::mask(xlib::ControlMask)
         )
     }
@@ -119,7 +266,37 @@ mod test {
     #[test]
     fn parse_mutliple() {
         let key = Key::from_str("ctrl + a").unwrap();
-        assert_eq!(key.mask, xlib::ControlMask);
-        assert_eq!(key.sym, into_keysym("a").unwrap());
+        assert_eq!({ key.mask }, xlib::ControlMask);
+        assert_eq!({ key.sym }, into_keysym("a").unwrap());
+    }
+
+    #[test]
+    fn parse_flags() {
+        let key = Key::from_str("hold + super + space").unwrap();
+        assert_eq!(key.flags(), HOLD);
+        assert_eq!({ key.without_flags().mask }, xlib::Mod4Mask);
+        assert_eq!(key.to_string(), "hold + super + space");
+
+        let key = Key::from_str("@Super_L").unwrap();
+        assert_eq!(key.flags(), RELEASE);
+        assert_eq!(key.to_string(), "@Super_L");
+    }
+
+    #[test]
+    fn aliases() {
+        let mut aliases = Aliases::default();
+        aliases.set("mod3", "hyper").unwrap();
+        assert!(aliases.set("mod6", "hyper").is_err());
+        let key = Key::parse("hyper + super + h", &aliases).unwrap();
+        assert_eq!({ key.mask }, xlib::Mod3Mask | xlib::Mod4Mask);
+        assert!(Key::from_str("hyper + h").is_err());
+        assert_eq!(Locks::new(&aliases).num, Some(xlib::Mod2Mask));
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        let key = Key::from_str("ctrl + shift + Return").unwrap();
+        assert_eq!(key.to_string(), "shift + ctrl + Return");
+        assert_eq!(Key::from_str(&key.to_string()).unwrap(), key);
     }
 }
@@ -1,101 +1,555 @@
-use std::{io, mem::MaybeUninit, os::unix::io::RawFd, ptr::NonNull};
+use std::{
+    ffi::{CStr, CString},
+    io,
+    mem::MaybeUninit,
+    os::{
+        raw::{c_int, c_uchar, c_ulong},
+        unix::io::RawFd,
+    },
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    },
+};
 
-use super::key::Key;
+use super::{key::Key, selection::Selections, window::WindowInfo};
 
 use mio::{event::Source, unix::SourceFd};
 
 use x11::xlib::{
-    AnyKey, AnyModifier, BadAccess as BAD_ACCESS, BadValue as BAD_VALUE, BadWindow as BAD_WINDOW,
-    Display, KeyPress as KEY_PRESS, KeyRelease as KEY_RELEASE, Window, XCloseDisplay,
-    XConnectionNumber, XDefaultScreenOfDisplay, XEvent, XGrabKey, XKeyPressedEvent,
-    XKeyReleasedEvent, XKeycodeToKeysym, XKeysymToKeycode, XNextEvent, XOpenDisplay, XPending,
-    XRootWindowOfScreen, XUngrabKey,
+    AnyKey, AnyModifier, AnyPropertyType, AsyncKeyboard, Atom, BadAccess as BAD_ACCESS,
+    BadValue as BAD_VALUE, BadWindow as BAD_WINDOW, Display, KeyPress as KEY_PRESS,
+    KeyRelease as KEY_RELEASE, PropertyChangeMask, PropertyNotify as PROPERTY_NOTIFY,
+    ReplayKeyboard, SelectionClear as SELECTION_CLEAR, SelectionNotify as SELECTION_NOTIFY,
+    SelectionRequest as SELECTION_REQUEST, Success, SyncKeyboard, Window, XAllowEvents, XClassHint,
+    XCloseDisplay, XConnectionNumber, XDefaultScreen, XDisplayString, XErrorEvent, XEvent,
+    XEventsQueued, XFetchName, XFlush, XFree, XGetClassHint, XGetWindowProperty, XGrabKey,
+    XGrabKeyboard, XInternAtom, XKeyPressedEvent, XKeyReleasedEvent, XKeycodeToKeysym,
+    XKeysymToKeycode, XNextEvent, XOpenDisplay, XPeekEvent, XPending, XPropertyEvent, XRootWindow,
+    XScreenCount, XSelectInput, XSelectionClearEvent, XSelectionEvent, XSelectionRequestEvent,
+    XSetErrorHandler, XSetIOErrorHandler, XUngrabKey, XUngrabKeyboard, XkbSetDetectableAutoRepeat,
 };
 
+pub use x11::xlib::Time;
+
+/// Shift, Lock, Control and Mod1-5, the pointer buttons and the XKB group are also reported in
+/// the state of the key events but never part of a hotkey
+const CORE_MODIFIERS: u32 = 0xff;
+
 #[derive(Debug)]
 
 pub struct DisplayContext {
     display: NonNull<Display>,
-    root: Window,
+    /// Name of the display, e.g. `:0`
+    name: String,
+    /// Root window of every screen, indexed by screen number
+    roots: Vec<Window>,
+    /// screen the requests apply to, see [`DisplayContext::set_screen`]
+    screen: usize,
     fd: RawFd,
+    atoms: Atoms,
+    pub(crate) selections: Selections,
+    /// set once the connection to the server is lost, boxed for the IO error exit handler
+    lost: Box<AtomicBool>,
+}
+
+enum Property {
+    Bytes(Vec<u8>),
+    Longs(Vec<c_ulong>),
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Atoms {
+    active_window: Atom,
+    wm_name: Atom,
+    wm_role: Atom,
 }
 
 pub struct Keyboard<'a> {
     display: &'a mut DisplayContext,
     event: MaybeUninit<XEvent>,
+    /// keycode of the last press until it is released or replayed, only the last key pressed
+    /// is repeated by the autorepeat
+    held: Option<u32>,
 }
 
 pub enum Event {
-    KeyPress(Key),
-    KeyRelease(Key),
+    KeyPress(KeyEvent),
+    KeyRelease(KeyEvent),
+    /// The focused window changed, see [`Keyboard::active_window`]
+    ActiveWindow,
+    /// A selection was converted, see [`DisplayContext::convert_selection`]
+    Selection,
     Other,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EventKind {
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// Keycode of the key, several keycodes can share the keysym of [`KeyEvent::key`]
+    pub keycode: u32,
+    /// X server time of the event, in milliseconds
+    pub time: Time,
+    /// Press generated by the autorepeat of a held key
+    pub repeat: bool,
+    /// Screen the key was pressed on
+    pub screen: usize,
+}
+
 impl DisplayContext {
+    /// Log the X11 errors instead of exiting on errors such as querying a destroyed window, and
+    /// the loss of a connection. The error handlers of Xlib are global to the process, so this is
+    /// left to the program embedding the library and only done once.
+    pub fn install_handlers() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| unsafe {
+            XSetErrorHandler(Some(error_handler));
+            XSetIOErrorHandler(Some(io_error_handler));
+        });
+    }
+
     /// # Errors
     /// Will throw an error if the file can't be open
     pub fn current() -> io::Result<Self> {
+        Self::open(None)
+    }
+
+    /// Connect to a display such as `:1`, `$DISPLAY` when `None`
+    pub fn open(name: Option<&str>) -> io::Result<Self> {
+        let name = name
+            .map(CString::new)
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
         unsafe {
-            let display = NonNull::new(XOpenDisplay(std::ptr::null())).ok_or({
+            let name = name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+            let display = NonNull::new(XOpenDisplay(name)).ok_or({
                 error!("unable to access X11 server");
                 io::Error::new(
                     io::ErrorKind::AddrNotAvailable,
                     "unable to access X11 server",
                 )
             })?;
-            let root = XRootWindowOfScreen(XDefaultScreenOfDisplay(display.as_ptr()));
+            let roots = (0..XScreenCount(display.as_ptr()))
+                .map(|screen| XRootWindow(display.as_ptr(), screen))
+                .collect::<Vec<_>>();
+            let screen = XDefaultScreen(display.as_ptr()) as usize;
+            let root = roots[screen];
+            let name = CStr::from_ptr(XDisplayString(display.as_ptr()))
+                .to_string_lossy()
+                .into_owned();
             let fd = XConnectionNumber(display.as_ptr());
+            let atoms = Atoms {
+                active_window: intern(display.as_ptr(), "_NET_ACTIVE_WINDOW"),
+                wm_name: intern(display.as_ptr(), "_NET_WM_NAME"),
+                wm_role: intern(display.as_ptr(), "WM_WINDOW_ROLE"),
+            };
+            let selections = Selections::new(display.as_ptr(), root);
+            let lost = Box::new(AtomicBool::new(false));
+            set_io_error_exit_handler(display.as_ptr(), &lost);
 
-            trace!("connected to X11 server");
-            Ok(Self { display, root, fd })
+            trace!(
+                "connected to X11 server {} | screens: {}",
+                name,
+                roots.len()
+            );
+            Ok(Self {
+                display,
+                name,
+                roots,
+                screen,
+                fd,
+                atoms,
+                selections,
+                lost,
+            })
         }
     }
 
+    /// Open a new connection to the same display, e.g. once the previous one is lost
+    pub fn reconnect(&mut self) -> io::Result<()> {
+        let name = self.name.clone();
+        *self = Self::open(Some(&name))?;
+        Ok(())
+    }
+
+    /// Whether the connection to the server is still up, no request should be made on a lost
+    /// connection and a new [`DisplayContext`] has to be opened
+    pub fn is_connected(&self) -> bool {
+        !self.lost.load(Ordering::Relaxed)
+    }
+
     pub fn display_mut(&mut self) -> &mut Display {
         unsafe { self.display.as_mut() }
     }
+
+    /// Root window of the current screen
+    pub fn root(&self) -> Window {
+        self.roots[self.screen]
+    }
+
+    /// Screen of the focused window, the pointer and the window manager requests, the default
+    /// screen until a key is pressed on another one
+    pub fn screen(&self) -> usize {
+        self.screen
+    }
+
+    pub fn set_screen(&mut self, screen: usize) {
+        if screen < self.roots.len() {
+            self.screen = screen;
+        }
+    }
+
+    /// Root windows of every screen
+    pub fn roots(&self) -> &[Window] {
+        &self.roots
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Screen of a root window
+    pub fn screen_of(&self, root: Window) -> Option<usize> {
+        self.roots.iter().position(|&r| r == root)
+    }
+
+    /// `DISPLAY` of a screen, e.g. `:0.1`
+    pub fn screen_name(&self, screen: usize) -> String {
+        // drop the screen of the name we connected with
+        let display = match self.name.rfind(':') {
+            Some(colon) => match self.name[colon..].find('.') {
+                Some(dot) => &self.name[..colon + dot],
+                None => &self.name,
+            },
+            None => &self.name,
+        };
+        format!("{}.{}", display, screen)
+    }
+
+    /// Content of a window property, `None` if it is not set
+    fn property(&mut self, window: Window, atom: Atom) -> Option<Property> {
+        let mut kind = 0;
+        let mut format = 0;
+        let mut items = 0;
+        let mut after = 0;
+        let mut data: *mut c_uchar = ptr::null_mut();
+        let status = unsafe {
+            XGetWindowProperty(
+                self.display_mut(),
+                window,
+                atom,
+                0,
+                1024,
+                i32::from(false),
+                AnyPropertyType as Atom,
+                &mut kind,
+                &mut format,
+                &mut items,
+                &mut after,
+                &mut data,
+            )
+        };
+        if status != i32::from(Success) || data.is_null() {
+            return None;
+        }
+        let property = unsafe {
+            match format {
+                8 => Property::Bytes(std::slice::from_raw_parts(data, items as usize).to_vec()),
+                // format 32 properties are returned as longs
+                32 => Property::Longs(
+                    std::slice::from_raw_parts(data as *const c_ulong, items as usize).to_vec(),
+                ),
+                _ => Property::Other,
+            }
+        };
+        unsafe { XFree(data.cast()) };
+        Some(property)
+    }
+
+    pub(crate) fn property_string(&mut self, window: Window, atom: Atom) -> Option<String> {
+        match self.property(window, atom)? {
+            Property::Bytes(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Properties of the window currently focused according to `_NET_ACTIVE_WINDOW`
+    pub fn active_window(&mut self) -> Option<WindowInfo> {
+        let (root, atoms) = (self.root(), self.atoms);
+        let id = match self.property(root, atoms.active_window)? {
+            Property::Longs(windows) => *windows.first()?,
+            _ => return None,
+        };
+        if id == 0 {
+            return None;
+        }
+
+        let mut info = WindowInfo {
+            id,
+            ..WindowInfo::default()
+        };
+        unsafe {
+            let mut hint = XClassHint {
+                res_name: ptr::null_mut(),
+                res_class: ptr::null_mut(),
+            };
+            if XGetClassHint(self.display_mut(), id, &mut hint) != 0 {
+                info.instance = take_xstring(hint.res_name).unwrap_or_default();
+                info.class = take_xstring(hint.res_class).unwrap_or_default();
+            }
+        }
+        info.name = match self.property_string(id, atoms.wm_name) {
+            Some(name) => name,
+            None => unsafe {
+                let mut name = ptr::null_mut();
+                XFetchName(self.display_mut(), id, &mut name);
+                take_xstring(name).unwrap_or_default()
+            },
+        };
+        info.role = self.property_string(id, atoms.wm_role).unwrap_or_default();
+        Some(info)
+    }
+}
+
+pub(crate) unsafe fn intern(display: *mut Display, name: &str) -> Atom {
+    let name = CString::new(name).expect("couldn't create new cstring");
+    XInternAtom(display, name.as_ptr(), i32::from(false))
+}
+
+/// Load a symbol of an optional X library, e.g. `libXtst.so.6`, at runtime
+pub(crate) fn load_symbol(lib: &str, name: &str) -> Option<*mut std::os::raw::c_void> {
+    let lib = CString::new(lib).ok()?;
+    let name = CString::new(name).ok()?;
+    unsafe {
+        let handle = libc::dlopen(lib.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return None;
+        }
+        let symbol = libc::dlsym(handle, name.as_ptr());
+        (!symbol.is_null()).then_some(symbol)
+    }
+}
+
+/// Copy and free a string allocated by Xlib
+unsafe fn take_xstring(s: *mut std::os::raw::c_char) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let res = CStr::from_ptr(s).to_string_lossy().into_owned();
+    XFree(s.cast());
+    Some(res)
+}
+
+type IOErrorExitHandler = unsafe extern "C" fn(*mut Display, *mut libc::c_void);
+type SetIOErrorExitHandler =
+    unsafe extern "C" fn(*mut Display, Option<IOErrorExitHandler>, *mut libc::c_void);
+
+/// Xlib calls `exit()` once the connection is lost unless an exit handler, added in libX11 1.7,
+/// returns instead. `lost` is then set for the daemon to reconnect. Unlike the other handlers,
+/// the exit handler belongs to the connection.
+unsafe fn set_io_error_exit_handler(display: *mut Display, lost: &AtomicBool) {
+    static OLD_XLIB: Once = Once::new();
+    match load_symbol("libX11.so.6", "XSetIOErrorExitHandler") {
+        Some(f) => {
+            let set = std::mem::transmute::<*mut libc::c_void, SetIOErrorExitHandler>(f);
+            let lost = lost as *const AtomicBool as *mut libc::c_void;
+            set(display, Some(io_error_exit_handler), lost);
+        }
+        None => OLD_XLIB.call_once(|| {
+            warn!("libX11 older than 1.7, exiting if the connection to the server is lost")
+        }),
+    }
+}
+
+unsafe extern "C" fn io_error_handler(display: *mut Display) -> c_int {
+    let name = CStr::from_ptr(XDisplayString(display)).to_string_lossy();
+    error!("lost the connection to X11 server {}", name);
+    0
+}
+
+unsafe extern "C" fn io_error_exit_handler(_: *mut Display, lost: *mut libc::c_void) {
+    (*(lost as *const AtomicBool)).store(true, Ordering::Relaxed);
+}
+
+unsafe extern "C" fn error_handler(_: *mut Display, event: *mut XErrorEvent) -> c_int {
+    let event = &*event;
+    warn!(
+        "X11 error {} on request {} for resource {:#x}",
+        event.error_code, event.request_code, event.resourceid
+    );
+    0
 }
 
 impl<'a> Keyboard<'a> {
     pub fn new(display: &'a mut DisplayContext) -> Self {
         let event = MaybeUninit::zeroed();
-        Self { display, event }
+        let mut keyboard = Self {
+            display,
+            event,
+            held: None,
+        };
+        keyboard.setup();
+        keyboard
+    }
+
+    /// Replace a lost connection by a new one to the same display, the keys have to be
+    /// grabbed again, see [`crate::Controler::reconnect`]
+    pub fn reconnect(&mut self) -> io::Result<()> {
+        self.display.reconnect()?;
+        self.held = None;
+        self.setup();
+        Ok(())
+    }
+
+    fn setup(&mut self) {
+        let display = &mut *self.display;
+        // get notified of the focused window changes
+        for root in display.roots.clone() {
+            unsafe { XSelectInput(display.display_mut(), root, PropertyChangeMask) };
+        }
+        // autorepeat only sends presses instead of release/press pairs
+        let mut supported = 0;
+        unsafe {
+            XkbSetDetectableAutoRepeat(display.display_mut(), i32::from(true), &mut supported)
+        };
+        if supported == 0 {
+            warn!("detectable autorepeat not supported, pairing release/press events");
+        }
+    }
+
+    pub fn context(&mut self) -> &mut DisplayContext {
+        self.display
     }
 
     pub fn grab_key(&mut self, key: Key) -> io::Result<()> {
+        self.grab(key, x11::xlib::GrabModeAsync)
+    }
+
+    /// Grab a key freezing the keyboard until [`Keyboard::allow_events`] decides whether the
+    /// event is replayed to the focused window.
+    pub fn grab_key_sync(&mut self, key: Key) -> io::Result<()> {
+        self.grab(key, x11::xlib::GrabModeSync)
+    }
+
+    /// Thaw the keyboard after a key grabbed with [`Keyboard::grab_key_sync`], `replay` sends the
+    /// event to the focused window as if it was not grabbed.
+    pub fn allow_events(&mut self, replay: bool, time: Time) {
+        trace!("allowing events | replay: {}", replay);
+        let mode = if replay {
+            ReplayKeyboard
+        } else {
+            AsyncKeyboard
+        };
+        if replay {
+            // the focused window gets the release
+            self.held = None;
+        }
+        unsafe {
+            XAllowEvents(self.display.display_mut(), mode, time);
+            XFlush(self.display.display_mut());
+        }
+    }
+
+    /// Thaw the keyboard for the next key event only, it is then reported to us even if it is
+    /// not grabbed and can still be replayed with [`Keyboard::allow_events`].
+    pub fn allow_next_event(&mut self, time: Time) {
+        trace!("allowing the next event");
+        unsafe {
+            XAllowEvents(self.display.display_mut(), SyncKeyboard, time);
+            XFlush(self.display.display_mut());
+        }
+    }
+
+    /// See [`DisplayContext::active_window`]
+    pub fn active_window(&mut self) -> Option<WindowInfo> {
+        self.display.active_window()
+    }
+
+    fn grab(&mut self, key: Key, keyboard_mode: c_int) -> io::Result<()> {
         trace!("grabing {:?}", key);
 
-        let err = unsafe {
-            let code = XKeysymToKeycode(self.display.display_mut(), key.sym);
-            XGrabKey(
+        let code = unsafe { XKeysymToKeycode(self.display.display_mut(), key.sym) };
+        let mut res = Ok(());
+        for root in self.display.roots.clone() {
+            let err = unsafe {
+                XGrabKey(
+                    self.display.display_mut(),
+                    i32::from(code),
+                    key.mask,
+                    root,
+                    i32::from(true),
+                    x11::xlib::GrabModeSync,
+                    keyboard_mode,
+                )
+            };
+            res = res.and(match err as u8 {
+                BAD_ACCESS => Err(io::Error::other(format!("X11 BadAccess: {:?}", key))),
+                BAD_VALUE => Err(io::Error::other(format!("X11 BadValue: {:?}", key))),
+                BAD_WINDOW => Err(io::Error::other(format!("X11 BadWindow: {:?}", key))),
+                _ => Ok(()),
+            });
+        }
+        if let Err(ref e) = res {
+            error!("unable to grab {:?}, {}", key, e);
+        }
+        res
+    }
+
+    pub fn ungrab_key(&mut self, key: Key) {
+        trace!("ungrabing {:?}", key);
+        let code = unsafe { XKeysymToKeycode(self.display.display_mut(), key.sym) };
+        for root in self.display.roots.clone() {
+            unsafe { XUngrabKey(self.display.display_mut(), i32::from(code), key.mask, root) };
+        }
+    }
+
+    /// Release every key grabbed on the root windows
+    pub fn ungrab_all(&mut self) {
+        trace!("ungrabing all keys");
+        for root in self.display.roots.clone() {
+            unsafe { XUngrabKey(self.display.display_mut(), AnyKey, AnyModifier, root) };
+        }
+    }
+
+    /// Actively grab the whole keyboard, every key event is reported to us until
+    /// [`Keyboard::ungrab_keyboard`] is called.
+    pub fn grab_keyboard(&mut self) -> io::Result<()> {
+        trace!("grabing keyboard");
+        let status = unsafe {
+            XGrabKeyboard(
                 self.display.display_mut(),
-                i32::from(code),
-                key.mask,
-                self.display.root,
-                i32::from(true),
-                x11::xlib::GrabModeSync,
+                self.display.root(),
+                i32::from(false),
+                x11::xlib::GrabModeAsync,
                 x11::xlib::GrabModeAsync,
+                x11::xlib::CurrentTime,
             )
         };
-        let res = match err as u8 {
-            BAD_ACCESS => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("X11 BadAccess: {:?}", key),
-            )),
-            BAD_VALUE => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("X11 BadValue: {:?}", key),
-            )),
-            BAD_WINDOW => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("X11 BadWindow: {:?}", key),
-            )),
-            _ => Ok(()),
-        };
-        if let Err(ref e) = res {
-            error!("unable to grab {:?}, {}", key, e);
+        match status {
+            x11::xlib::GrabSuccess => Ok(()),
+            x11::xlib::AlreadyGrabbed => Err(io::Error::other("keyboard already grabbed")),
+            status => Err(io::Error::other(format!(
+                "unable to grab keyboard: {}",
+                status
+            ))),
         }
-        res
+    }
+
+    pub fn ungrab_keyboard(&mut self) {
+        trace!("ungrabing keyboard");
+        unsafe { XUngrabKeyboard(self.display.display_mut(), x11::xlib::CurrentTime) };
+    }
+
+    pub fn keysym_to_keycode(&mut self, sym: u64) -> u8 {
+        unsafe { XKeysymToKeycode(self.display.display_mut(), sym) }
     }
 
     pub fn read_events(&mut self, buf: &mut Vec<Event>) {
@@ -107,35 +561,123 @@ impl<'a> Keyboard<'a> {
         }
     }
 
+    /// Whether events are waiting to be read, including the ones moved to the queue of Xlib by
+    /// the round trips of the actions which don't make the connection readable again
+    pub fn has_events(&mut self) -> bool {
+        const QUEUED_AFTER_FLUSH: c_int = 2;
+        self.display.is_connected()
+            && unsafe { XEventsQueued(self.display.display_mut(), QUEUED_AFTER_FLUSH) } > 0
+    }
+
     pub fn read_event(&mut self) {
         trace!("reading X11 event");
         unsafe { XNextEvent(self.display.display_mut(), self.event.as_mut_ptr()) };
     }
 
+    /// # Safety
+    /// An event must have been read with [`Keyboard::read_event`] beforehand
     pub unsafe fn decode_event(&mut self) -> Event {
         trace!("decoding X11 event");
         let event = &*self.event.as_ptr();
         match event.get_type() {
             KEY_PRESS => {
-                let (sym, mask) = {
+                let (sym, mask, time, code, root) = {
                     let event = XKeyPressedEvent::from(event);
                     let sym = self.keycode_to_keysym(event.keycode);
-                    (sym, event.state)
+                    (
+                        sym,
+                        event.state & CORE_MODIFIERS,
+                        event.time,
+                        event.keycode,
+                        event.root,
+                    )
                 };
-                Event::KeyPress(Key { sym, mask })
+                let repeat = self.held.replace(code) == Some(code);
+                Event::KeyPress(KeyEvent {
+                    key: Key { sym, mask },
+                    keycode: code,
+                    time,
+                    repeat,
+                    screen: self.display.screen_of(root).unwrap_or(0),
+                })
             }
             KEY_RELEASE => {
-                let (sym, mask) = {
+                let (sym, mask, time, code, root) = {
                     let event = XKeyReleasedEvent::from(event);
                     let sym = self.keycode_to_keysym(event.keycode);
-                    (sym, event.state)
+                    (
+                        sym,
+                        event.state & CORE_MODIFIERS,
+                        event.time,
+                        event.keycode,
+                        event.root,
+                    )
                 };
-                Event::KeyRelease(Key { sym, mask })
+                if self.is_autorepeat(code, time) {
+                    return Event::Other;
+                }
+                if self.held == Some(code) {
+                    self.held = None;
+                }
+                Event::KeyRelease(KeyEvent {
+                    key: Key { sym, mask },
+                    keycode: code,
+                    time,
+                    repeat: false,
+                    screen: self.display.screen_of(root).unwrap_or(0),
+                })
+            }
+            SELECTION_REQUEST => {
+                let request = XSelectionRequestEvent::from(event);
+                self.display.serve_selection(&request);
+                Event::Other
+            }
+            SELECTION_NOTIFY => {
+                let notify = XSelectionEvent::from(event);
+                match self.display.selection_notify(&notify) {
+                    true => Event::Selection,
+                    false => Event::Other,
+                }
+            }
+            SELECTION_CLEAR => {
+                let clear = XSelectionClearEvent::from(event);
+                self.display.clear_selection(clear.selection);
+                Event::Other
+            }
+            PROPERTY_NOTIFY => {
+                let event = XPropertyEvent::from(event);
+                // the focused window of the other screens is read once a key is pressed there
+                if event.atom == self.display.atoms.active_window
+                    && event.window == self.display.root()
+                {
+                    Event::ActiveWindow
+                } else if self.display.selection_chunk(&event) {
+                    Event::Selection
+                } else {
+                    Event::Other
+                }
             }
             _ => Event::Other,
         }
     }
 
+    /// Without detectable autorepeat, a release immediately followed by a press of the same key
+    /// at the same time is generated by the autorepeat.
+    unsafe fn is_autorepeat(&mut self, code: u32, time: Time) -> bool {
+        const QUEUED_AFTER_READING: c_int = 1;
+        if XEventsQueued(self.display.display_mut(), QUEUED_AFTER_READING) == 0 {
+            return false;
+        }
+        let mut next = MaybeUninit::<XEvent>::zeroed();
+        XPeekEvent(self.display.display_mut(), next.as_mut_ptr());
+        let next = &*next.as_ptr();
+        if next.get_type() != KEY_PRESS {
+            return false;
+        }
+        let next = XKeyPressedEvent::from(next);
+        next.keycode == code && next.time == time
+    }
+
     #[allow(clippy::cast_possible_truncation)]
     fn keycode_to_keysym(&mut self, code: u32) -> u64 {
         unsafe { XKeycodeToKeysym(self.display.display_mut(), code as u8, 0) }
@@ -174,13 +716,8 @@ impl Drop for DisplayContext {
 
 impl<'a> Drop for Keyboard<'a> {
     fn drop(&mut self) {
-        unsafe {
-            XUngrabKey(
-                self.display.display_mut(),
-                AnyKey,
-                AnyModifier,
-                self.display.root,
-            )
-        };
+        if self.display.is_connected() {
+            self.ungrab_all();
+        }
     }
 }
@@ -0,0 +1,87 @@
+//! Asynchronous Rust X11 Hotkey Daemon
+//!
+//! The hotkey engine behind the `rhkd` binary: connect to the X server with a
+//! [`DisplayContext`], grab bindings on its [`Keyboard`] through a [`Builder`] and feed the
+//! decoded [`Event`]s to the resulting [`Controler`].
+//!
+//! The [`Controler`] never blocks: the connection is polled with the [`Controler::timeout`] of
+//! its delays and each wake up is handed to [`Controler::dispatch`], with the children reaped
+//! since the previous one.
+//!
+//! ```no_run
+//! extern crate mio;
+//! extern crate rhkd;
+//!
+//! use mio::{Events, Interest, Poll, Token};
+//! use rhkd::{process, Builder, DisplayContext, Keyboard, Request};
+//!
+//! // log the X11 errors instead of exiting
+//! DisplayContext::install_handlers();
+//! let mut context = DisplayContext::current().unwrap();
+//! let mut keyboard = Keyboard::new(&mut context);
+//!
+//! let mut builder = Builder::new(&mut keyboard);
+//! builder.bind("super + e", "alacritty");
+//!
+//! let mut count = 0;
+//! builder.bind(
+//!     "super + c",
+//!     Box::new(move |ctx: &mut rhkd::Context| {
+//!         count += 1;
+//!         println!("{} pressed {} times", ctx.key, count);
+//!         Ok(())
+//!     }),
+//! );
+//! let mut ctrl = builder.finish("/tmp/rhkd.fst").unwrap();
+//!
+//! let mut poll = Poll::new().unwrap();
+//! poll.registry()
+//!     .register(&mut keyboard, Token(0), Interest::READABLE)
+//!     .unwrap();
+//! let mut events = Events::with_capacity(8);
+//! loop {
+//!     poll.poll(&mut events, ctrl.timeout()).unwrap();
+//!     // the daemon only does this on SIGCHLD
+//!     let mut exited = process::wait_children();
+//!     for request in ctrl.dispatch(&mut keyboard, &mut exited) {
+//!         match request {
+//!             Request::Quit => return,
+//!             request => println!("{:?} is left to the caller", request),
+//!         }
+//!     }
+//! }
+//! ```
+
+extern crate fst;
+extern crate libc;
+extern crate memmap;
+extern crate mio;
+extern crate x11;
+
+#[macro_use]
+extern crate log;
+
+pub mod bspwm;
+pub mod controler;
+pub mod ewmh;
+pub mod exec;
+pub mod i3;
+pub mod key;
+pub mod keyboard;
+pub mod options;
+pub mod pointer;
+pub mod process;
+pub mod selection;
+pub mod template;
+pub mod window;
+pub mod xtest;
+
+pub use controler::{Builder, Controler, Handle, Request};
+pub use ewmh::WindowState;
+pub use exec::{Context, Exec, ExecHandle, Flow, Handler, IntoExec, Sequence};
+pub use key::Key;
+pub use keyboard::{DisplayContext, Event, EventKind, KeyEvent, Keyboard};
+pub use options::{Options, Output, Policy, Repeat};
+pub use selection::Selection;
+pub use template::Template;
+pub use window::{WindowInfo, WindowMatch};
@@ -1,5 +1,5 @@
-extern crate fst;
 extern crate mio;
+extern crate rhkd;
 extern crate signal_hook;
 extern crate signal_hook_mio;
 extern crate x11;
@@ -8,24 +8,29 @@ extern crate x11;
 extern crate log;
 
 mod binds;
-mod controler;
-mod exec;
-mod key;
-mod keyboard;
+mod control;
+mod inspect;
 
-use std::{env, io};
+use std::{
+    env, io, mem,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use binds::bind;
-use controler::Builder;
-use keyboard::{DisplayContext, Event, Keyboard};
+use control::ControlSocket;
+use rhkd::{controler::Request, process, Builder, Controler, DisplayContext, Keyboard};
 
 use mio::{Events, Interest, Poll, Token};
 use signal_hook::consts::signal::*;
 use signal_hook_mio::v0_7::Signals;
 
 const HELP: &str = "Rust X11 Hotkey Daemon
-    --help          Help string
-    --fst <PATH>    Path in which to store the fst";
+    --help              Help string
+    --fst <PATH>        Path in which to store the fst
+    --socket <PATH>     Path of the control socket (default: $XDG_RUNTIME_DIR/rhkd.socket)
+    --display <LIST>    Comma separated displays to serve, e.g. :0,:1 (default: $DISPLAY)
+    --inspect           Print the hotkey of every key pressed (Escape to quit)";
 
 fn exit() -> ! {
     eprintln!("{}", HELP);
@@ -35,6 +40,9 @@ fn exit() -> ! {
 #[derive(Default)]
 struct Args {
     fst: Option<String>,
+    socket: Option<String>,
+    displays: Vec<String>,
+    inspect: bool,
 }
 
 fn argparse() -> Args {
@@ -48,14 +56,33 @@ fn argparse() -> Args {
                 std::process::exit(1)
             }
             "--fst" => output.fst = args.next().ok_or_else(exit).ok(),
+            "--socket" => output.socket = args.next().ok_or_else(exit).ok(),
+            "--display" => {
+                let list = args.next().unwrap_or_else(|| exit());
+                output.displays = list.split(',').map(str::to_string).collect();
+            }
+            "--inspect" => output.inspect = true,
             _ => exit(),
         }
     }
     output
 }
 
+/// Delays between the attempts to reconnect to a lost display
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Next attempt to reconnect to a lost display
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    at: Instant,
+    backoff: Duration,
+}
+
 const SIGNAL: Token = Token(0);
-const KEYBOARD: Token = Token(1);
+const CONTROL: Token = Token(1);
+/// token of the first display, the next ones follow
+const KEYBOARD: Token = Token(2);
 
 fn main() -> io::Result<()> {
     pretty_env_logger::init_timed();
@@ -63,29 +90,58 @@ fn main() -> io::Result<()> {
     let mut poll = Poll::new()?;
     let args = argparse();
 
-    let mut context = DisplayContext::current().unwrap();
-    let mut keyboard = Keyboard::new(&mut context);
+    let mut contexts = open(&args)?;
+    let mut keyboards = contexts.iter_mut().map(Keyboard::new).collect::<Vec<_>>();
 
-    let mut builder = Builder::new(&mut keyboard);
-    bind(&mut builder);
-    let mut ctrl = builder.finish(args.fst.as_deref().unwrap_or("/tmp/rhkb.fst"))?;
+    if args.inspect {
+        return inspect::inspect(&mut keyboards[0]);
+    }
+
+    let mut ctrls = keyboards
+        .iter_mut()
+        .enumerate()
+        .map(|(i, keyboard)| build(keyboard, &args, i))
+        .collect::<io::Result<Vec<_>>>()?;
 
-    let mut signals = Signals::new(&[SIGTERM, SIGINT])?;
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGUSR1, SIGUSR2, SIGCHLD])?;
+    let path = args
+        .socket
+        .as_ref()
+        .map_or_else(ControlSocket::default_path, PathBuf::from);
+    // the hotkeys still work without the control socket
+    let mut control = ControlSocket::bind(&path)
+        .map_err(|err| warn!("running without the control socket | {}", err))
+        .ok();
     {
         let registry = poll.registry();
         registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
-        registry.register(
-            &mut keyboard,
-            KEYBOARD,
-            Interest::READABLE | Interest::WRITABLE,
-        )?;
+        if let Some(control) = &mut control {
+            registry.register(control, CONTROL, Interest::READABLE)?;
+        }
+        for (i, keyboard) in keyboards.iter_mut().enumerate() {
+            register(&poll, keyboard, i)?;
+        }
     }
 
+    // displays whose connection was lost, the others are still served
+    let mut lost: Vec<Option<Retry>> = vec![None; keyboards.len()];
+    // children waiting for the controler that spawned them
+    let mut exited = Vec::new();
     let mut events = Events::with_capacity(32);
-    let mut xevents = Vec::with_capacity(32);
-    let mut collect = 0;
     loop {
-        match poll.poll(&mut events, None) {
+        let now = Instant::now();
+        let timeout = ctrls
+            .iter()
+            .zip(&lost)
+            .filter(|(_, lost)| lost.is_none())
+            .filter_map(|(ctrl, _)| ctrl.timeout())
+            .chain(
+                lost.iter()
+                    .flatten()
+                    .map(|retry| retry.at.saturating_duration_since(now)),
+            )
+            .min();
+        match poll.poll(&mut events, timeout) {
             Ok(_) => {}
             Err(a) if a.kind() == io::ErrorKind::Interrupted => {
                 continue;
@@ -96,27 +152,151 @@ fn main() -> io::Result<()> {
             match event.token() {
                 SIGNAL => {
                     trace!("signal event");
-                    return Ok(());
+                    for signal in signals.pending() {
+                        let request = match signal {
+                            SIGUSR1 => Request::Reload,
+                            SIGUSR2 => Request::ToggleGrabs,
+                            SIGCHLD => {
+                                exited.extend(process::wait_children());
+                                continue;
+                            }
+                            _ => return Ok(()),
+                        };
+                        for (i, keyboard) in keyboards.iter_mut().enumerate() {
+                            if lost[i].is_none() {
+                                ctrls[i].request(keyboard, request.clone());
+                            }
+                        }
+                    }
                 }
-                KEYBOARD => {
-                    trace!("keyboard event");
-                    collect += 1;
-
-                    keyboard.read_events(&mut xevents);
-                    xevents.drain(..).for_each(|event| {
-                        if let Event::KeyPress(key) = event {
-                            ctrl.execute(key);
+                CONTROL => {
+                    trace!("control event");
+                    let control = match &mut control {
+                        Some(control) => control,
+                        None => continue,
+                    };
+                    control.accept(|request| {
+                        for (i, keyboard) in keyboards.iter_mut().enumerate() {
+                            if lost[i].is_none() {
+                                ctrls[i].request(keyboard, request.clone());
+                            }
                         }
                     });
+                }
+                // the events of every keyboard are read below
+                _ => trace!("keyboard event"),
+            }
+        }
+
+        for (i, keyboard) in keyboards.iter_mut().enumerate() {
+            let retry = match lost[i] {
+                Some(retry) if retry.at <= Instant::now() => retry,
+                _ => continue,
+            };
+            lost[i] = match keyboard.reconnect() {
+                Ok(()) => {
+                    info!(
+                        "reconnected to {}, grabbing the bindings again",
+                        keyboard.context().name()
+                    );
+                    register(&poll, keyboard, i)?;
+                    ctrls[i].reconnect(keyboard);
+                    None
+                }
+                Err(_) => {
+                    info!(
+                        "reconnecting to {} in {:?}",
+                        keyboard.context().name(),
+                        retry.backoff
+                    );
+                    Some(Retry {
+                        at: Instant::now() + retry.backoff,
+                        backoff: (retry.backoff * 2).min(MAX_BACKOFF),
+                    })
+                }
+            };
+        }
 
-                    if collect >= 10 {
-                        info!("collecting zombies");
-                        collect = 0;
-                        zombie::collect_zombies();
+        for (i, keyboard) in keyboards.iter_mut().enumerate() {
+            if lost[i].is_some() {
+                continue;
+            }
+            // the connection is edge triggered, the queue of Xlib is emptied
+            for request in ctrls[i].dispatch(keyboard, &mut exited) {
+                match request {
+                    Request::Quit => return Ok(()),
+                    Request::Reload => {
+                        info!(
+                            "reloading bindings | display: {}",
+                            keyboard.context().name()
+                        );
+                        keyboard.ungrab_all();
+                        match build(keyboard, &args, i) {
+                            Ok(ctrl) => {
+                                let previous = mem::replace(&mut ctrls[i], ctrl);
+                                ctrls[i].take_over(previous);
+                            }
+                            Err(err) => {
+                                error!("unable to reload, keeping the bindings | {}", err);
+                                keyboard.ungrab_all();
+                                ctrls[i].grab(keyboard);
+                            }
+                        }
                     }
+                    request => warn!("unhandled request {:?}", request),
                 }
-                _ => {}
+            }
+            if !keyboard.context().is_connected() {
+                warn!(
+                    "lost the connection to {}, reconnecting",
+                    keyboard.context().name()
+                );
+                let _ = poll.registry().deregister(keyboard);
+                lost[i] = Some(Retry {
+                    at: Instant::now(),
+                    backoff: MIN_BACKOFF,
+                });
             }
         }
+
+        // the children of a lost display are handled once it is back
+        if lost.iter().all(Option::is_none) {
+            for (pid, status) in exited.drain(..) {
+                debug!("reaped untracked child | pid: {} | {}", pid, status);
+            }
+        }
+    }
+}
+
+/// Connect to the displays of `--display`, `$DISPLAY` by default
+fn open(args: &Args) -> io::Result<Vec<DisplayContext>> {
+    DisplayContext::install_handlers();
+    match args.displays.is_empty() {
+        true => Ok(vec![DisplayContext::current()?]),
+        false => args
+            .displays
+            .iter()
+            .map(|name| DisplayContext::open(Some(name)))
+            .collect(),
+    }
+}
+
+/// Register the connection of the `index`th display
+fn register(poll: &Poll, keyboard: &mut Keyboard, index: usize) -> io::Result<()> {
+    poll.registry().register(
+        keyboard,
+        Token(KEYBOARD.0 + index),
+        Interest::READABLE | Interest::WRITABLE,
+    )
+}
+
+/// Bind the hotkeys of the `index`th display, each display gets its own fst
+fn build(keyboard: &mut Keyboard, args: &Args, index: usize) -> io::Result<Controler> {
+    let mut builder = Builder::new(keyboard);
+    bind(&mut builder);
+    let path = args.fst.as_deref().unwrap_or("/tmp/rhkb.fst");
+    match index {
+        0 => builder.finish(path),
+        index => builder.finish(format!("{}.{}", path, index)),
     }
 }
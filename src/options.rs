@@ -0,0 +1,262 @@
+use std::{
+    ffi::OsString,
+    fs::OpenOptions,
+    io,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// Per binding settings, the unset ones fall back to the global defaults given to
+/// [`crate::Builder::defaults`]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub output: Option<Output>,
+    pub policy: Option<Policy>,
+    pub repeat: Option<Repeat>,
+    /// Variables set in the environment of the command, after the global ones
+    pub env: Vec<(OsString, OsString)>,
+    /// Start the command without the environment of the daemon
+    pub clear_env: Option<bool>,
+    /// Working directory of the command
+    pub cwd: Option<PathBuf>,
+    /// Start the command in a new session, out of the daemon's process group (default)
+    pub session: Option<bool>,
+    /// Fork twice so that the command is not a child of the daemon, which then can't follow it:
+    /// its exit isn't logged and it can't be combined with `timeout`, `kill_group` or a
+    /// [`Policy`] other than [`Policy::Allow`]
+    pub detach: Option<bool>,
+    /// Signal the whole process group of the command instead of the command only, requires
+    /// `session`
+    pub kill_group: Option<bool>,
+    /// Terminate the command if it is still running after this long
+    pub timeout: Option<Duration>,
+    /// Run the command line with `sh -c`, see [`crate::Template`]
+    pub shell: Option<bool>,
+}
+
+/// What to do with the presses generated by the autorepeat of a held hotkey
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Repeat {
+    /// Only run on the first press
+    Once,
+    /// Run on every press (default)
+    Always,
+    /// Run on the presses at least this long after the previous run
+    Interval(Duration),
+}
+
+/// What to do when a binding fires while the command it spawned previously is still running
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Policy {
+    /// Spawn another instance (default)
+    Allow,
+    /// Ignore the hotkey
+    Skip,
+    /// Terminate the running instances and spawn a new one
+    Restart,
+    /// Spawn once the running instances exited
+    Queue,
+}
+
+/// Where the stdout and stderr of a spawned command go
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Output {
+    /// Discard them
+    Null,
+    /// Forward them line by line to the log
+    Log,
+    /// Append them to a file
+    File(PathBuf),
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = Some(repeat);
+        self
+    }
+
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, value: V) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn clear_env(mut self, clear: bool) -> Self {
+        self.clear_env = Some(clear);
+        self
+    }
+
+    pub fn cwd<P: Into<PathBuf>>(mut self, cwd: P) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    pub fn session(mut self, session: bool) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn detach(mut self, detach: bool) -> Self {
+        self.detach = Some(detach);
+        self
+    }
+
+    pub fn kill_group(mut self, kill_group: bool) -> Self {
+        self.kill_group = Some(kill_group);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn shell(mut self, shell: bool) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Fill the unset settings with the ones of `defaults`
+    pub fn merge(self, defaults: &Options) -> Self {
+        Self {
+            output: self.output.or_else(|| defaults.output.clone()),
+            policy: self.policy.or(defaults.policy),
+            repeat: self.repeat.or(defaults.repeat),
+            env: defaults.env.iter().cloned().chain(self.env).collect(),
+            clear_env: self.clear_env.or(defaults.clear_env),
+            cwd: self.cwd.or_else(|| defaults.cwd.clone()),
+            session: self.session.or(defaults.session),
+            detach: self.detach.or(defaults.detach),
+            kill_group: self.kill_group.or(defaults.kill_group),
+            timeout: self.timeout.or(defaults.timeout),
+            shell: self.shell.or(defaults.shell),
+        }
+    }
+
+    /// Setting that needs the daemon to follow the command, which `detach` prevents
+    pub fn detach_conflict(&self) -> Option<&'static str> {
+        if self.detach != Some(true) {
+            return None;
+        }
+        if self.timeout.is_some() {
+            Some("timeout")
+        } else if self.kill_group == Some(true) {
+            Some("kill_group")
+        } else if self.policy.is_some_and(|policy| policy != Policy::Allow) {
+            Some("policy")
+        } else {
+            None
+        }
+    }
+
+    /// Start the command in a new session and/or detach it
+    fn pre_exec(&self, command: &mut Command) {
+        let session = self.session.unwrap_or(true);
+        let detach = self.detach.unwrap_or(false);
+        if !session && !detach {
+            return;
+        }
+        let pre_exec = move || {
+            if session && unsafe { libc::setsid() } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if detach {
+                match unsafe { libc::fork() } {
+                    -1 => return Err(io::Error::last_os_error()),
+                    0 => {}
+                    _ => unsafe { libc::_exit(0) },
+                }
+            }
+            Ok(())
+        };
+        // only async-signal-safe calls are made between fork and exec
+        unsafe { command.pre_exec(pre_exec) };
+    }
+
+    /// Apply the settings to a command before spawning it
+    pub fn prepare(&self, command: &mut Command) -> io::Result<()> {
+        match self.output.as_ref().unwrap_or(&Output::Null) {
+            Output::Null => {
+                command.stdout(Stdio::null()).stderr(Stdio::null());
+            }
+            Output::Log => {
+                command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            }
+            Output::File(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                command.stderr(file.try_clone()?).stdout(file);
+            }
+        }
+        if self.clear_env == Some(true) {
+            command.env_clear();
+        }
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(ref cwd) = self.cwd {
+            command.current_dir(cwd);
+        }
+        self.pre_exec(command);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge() {
+        let defaults = Options::new()
+            .env("A", "global")
+            .env("B", "global")
+            .cwd("/tmp");
+        let options = Options::new().env("B", "binding").merge(&defaults);
+        assert_eq!(options.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(options.clear_env, None);
+
+        let mut command = Command::new("true");
+        options.prepare(&mut command).unwrap();
+        let envs = command.get_envs().collect::<Vec<_>>();
+        assert!(envs.contains(&("A".as_ref(), Some("global".as_ref()))));
+        assert!(envs.contains(&("B".as_ref(), Some("binding".as_ref()))));
+    }
+
+    #[test]
+    fn detach_conflict() {
+        let detached = Options::new().detach(true);
+        assert_eq!(detached.detach_conflict(), None);
+        let options = Options::new().timeout(Duration::from_secs(1));
+        assert_eq!(options.merge(&detached).detach_conflict(), Some("timeout"));
+        let options = detached.clone().policy(Policy::Skip);
+        assert_eq!(options.detach_conflict(), Some("policy"));
+        let options = detached.policy(Policy::Allow);
+        assert_eq!(options.detach_conflict(), None);
+    }
+
+    #[test]
+    fn session() {
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        Options::new().prepare(&mut command).unwrap();
+        let mut child = command.spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        let pgid = unsafe { libc::getpgid(pid) };
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(pgid, pid);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    os::raw::{c_int, c_short},
+    sync::OnceLock,
+};
+
+use x11::xlib::{Display, XQueryPointer};
+
+use crate::keyboard::{load_symbol, DisplayContext};
+
+#[repr(C)]
+struct XineramaScreenInfo {
+    screen_number: c_int,
+    x_org: c_short,
+    y_org: c_short,
+    width: c_short,
+    height: c_short,
+}
+
+type QueryScreens = unsafe extern "C" fn(*mut Display, *mut c_int) -> *mut XineramaScreenInfo;
+
+/// `XineramaQueryScreens`, libXinerama is loaded on first use as it is only needed for the
+/// `{monitor}` placeholder
+fn query_screens() -> Option<QueryScreens> {
+    static QUERY_SCREENS: OnceLock<Option<QueryScreens>> = OnceLock::new();
+    *QUERY_SCREENS.get_or_init(|| {
+        let f = load_symbol("libXinerama.so.1", "XineramaQueryScreens")?;
+        Some(unsafe { std::mem::transmute::<*mut libc::c_void, QueryScreens>(f) })
+    })
+}
+
+/// Position of the pointer and the monitors
+impl DisplayContext {
+    /// Position of the pointer on the root window
+    pub fn pointer(&mut self) -> (i32, i32) {
+        let root = self.root();
+        let (mut root_return, mut child) = (0, 0);
+        let (mut x, mut y, mut win_x, mut win_y, mut mask) = (0, 0, 0, 0, 0);
+        unsafe {
+            XQueryPointer(
+                self.display_mut(),
+                root,
+                &mut root_return,
+                &mut child,
+                &mut x,
+                &mut y,
+                &mut win_x,
+                &mut win_y,
+                &mut mask,
+            )
+        };
+        (x, y)
+    }
+
+    /// Xinerama index of the monitor containing a point, `None` without Xinerama
+    pub fn monitor_at(&mut self, x: i32, y: i32) -> Option<i32> {
+        let query_screens = query_screens()?;
+        let mut count = 0;
+        let screens = unsafe { query_screens(self.display_mut(), &mut count) };
+        if screens.is_null() {
+            return None;
+        }
+        let monitor = unsafe { std::slice::from_raw_parts(screens, count as usize) }
+            .iter()
+            .find(|screen| {
+                let (left, top) = (i32::from(screen.x_org), i32::from(screen.y_org));
+                (left..left + i32::from(screen.width)).contains(&x)
+                    && (top..top + i32::from(screen.height)).contains(&y)
+            })
+            .map(|screen| screen.screen_number);
+        unsafe { x11::xlib::XFree(screens.cast()) };
+        monitor
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+    os::unix::process::ExitStatusExt,
+    process::{Child, ExitStatus},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{key::Key, selection::Selection};
+
+/// Delay between the `SIGTERM` and the `SIGKILL` of a command that timed out
+pub const KILL_DELAY: Duration = Duration::from_secs(5);
+/// How often the outputs still read after their child exited are checked
+pub const CAPTURE_POLL: Duration = Duration::from_millis(50);
+
+/// Child spawned by a binding
+#[derive(Debug)]
+pub struct Process {
+    /// Index of the binding's command
+    pub cmd: usize,
+    /// Hotkey that spawned the process
+    pub key: Key,
+    pub started: Instant,
+    pub child: Child,
+    /// signals are sent to the process group of the child, see [`crate::Options::kill_group`]
+    pub group: bool,
+    /// when to signal the child next, see [`crate::Options::timeout`]
+    pub deadline: Option<Instant>,
+    /// `SIGTERM` was sent after the timeout
+    pub terminated: bool,
+    /// stdout being read for a selection, see [`crate::Exec::Capture`]
+    capture: Option<Capture>,
+}
+
+/// Output of a child read for a selection, see [`crate::Exec::Capture`]
+#[derive(Debug)]
+pub struct Capture {
+    pub selection: Selection,
+    reader: JoinHandle<Vec<u8>>,
+}
+
+impl Capture {
+    /// Whether the output was read to the end, the pipe stays open after the child exited
+    /// while a process it started in the background holds it
+    pub fn is_done(&self) -> bool {
+        self.reader.is_finished()
+    }
+
+    /// Output without its trailing newlines, waits for [`Capture::is_done`]
+    pub fn output(self) -> String {
+        let output = self.reader.join().unwrap_or_default();
+        let output = String::from_utf8_lossy(&output);
+        output.trim_end_matches('\n').to_string()
+    }
+}
+
+impl Process {
+    pub fn new(cmd: usize, key: Key, child: Child) -> Self {
+        Self {
+            cmd,
+            key,
+            started: Instant::now(),
+            child,
+            group: false,
+            deadline: None,
+            terminated: false,
+            capture: None,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn runtime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Send `signal` to the child, or to its process group
+    pub fn signal(&self, signal: libc::c_int) {
+        debug!(
+            "signal {} | pid: {} | group: {}",
+            signal,
+            self.id(),
+            self.group
+        );
+        let pid = self.id() as libc::pid_t;
+        let pid = if self.group { -pid } else { pid };
+        unsafe { libc::kill(pid, signal) };
+    }
+
+    /// Read the piped stdout of the child to put it in `selection`
+    pub fn capture_output(&mut self, selection: Selection) {
+        if let Some(mut stdout) = self.child.stdout.take() {
+            let reader = thread::spawn(move || {
+                let mut output = Vec::new();
+                let _ = stdout.read_to_end(&mut output);
+                output
+            });
+            self.capture = Some(Capture { selection, reader });
+        }
+    }
+
+    /// Output being read by [`Process::capture_output`], to take once the child exited
+    pub fn captured(&mut self) -> Option<Capture> {
+        self.capture.take()
+    }
+
+    /// Forward the piped stdout and stderr of the child to the log
+    pub fn log_output(&mut self) {
+        if let Some(stdout) = self.child.stdout.take() {
+            log_lines(self.key, self.id(), "stdout", stdout);
+        }
+        if let Some(stderr) = self.child.stderr.take() {
+            log_lines(self.key, self.id(), "stderr", stderr);
+        }
+    }
+}
+
+fn log_lines<R: Read + Send + 'static>(key: Key, pid: u32, stream: &'static str, reader: R) {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            info!("{} | pid: {} | {}: {}", key, pid, stream, line);
+        }
+    });
+}
+
+/// Bookkeeping of the running children, reaped on `SIGCHLD`
+#[derive(Debug, Default)]
+pub struct Processes {
+    running: HashMap<u32, Process>,
+}
+
+impl Processes {
+    pub fn insert(&mut self, process: Process) {
+        self.running.insert(process.id(), process);
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Process> {
+        self.running.values_mut()
+    }
+
+    /// Running children spawned by the binding `cmd`
+    pub fn running(&self, cmd: usize) -> impl Iterator<Item = &Process> {
+        self.running
+            .values()
+            .filter(move |process| process.cmd == cmd)
+    }
+
+    /// Ask a child to terminate, it is reaped as usual once exited
+    pub fn kill(&mut self, pid: u32) {
+        if let Some(process) = self.running.get(&pid) {
+            process.signal(libc::SIGTERM);
+        }
+    }
+
+    /// Earliest deadline of the children with a timeout
+    pub fn deadline(&self) -> Option<Instant> {
+        self.running.values().filter_map(|p| p.deadline).min()
+    }
+
+    /// Terminate the children past their deadline, and kill the ones still running
+    /// [`KILL_DELAY`] later
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        for process in self.running.values_mut() {
+            if process.deadline.is_none_or(|deadline| deadline > now) {
+                continue;
+            }
+            if process.terminated {
+                warn!("killing {} | pid: {}", process.key, process.id());
+                process.signal(libc::SIGKILL);
+                process.deadline = None;
+            } else {
+                warn!("timed out {} | pid: {}", process.key, process.id());
+                process.signal(libc::SIGTERM);
+                process.terminated = true;
+                process.deadline = Some(now + KILL_DELAY);
+            }
+        }
+    }
+
+    /// Take the tracked children out of the ones [`wait_children`] reaped
+    pub fn reap(&mut self, exited: &mut Vec<(u32, ExitStatus)>) -> Vec<(Process, ExitStatus)> {
+        let mut reaped = Vec::new();
+        exited.retain(|&(pid, status)| match self.running.remove(&pid) {
+            Some(process) => {
+                reaped.push((process, status));
+                false
+            }
+            None => true,
+        });
+        reaped
+    }
+}
+
+/// Wait for every exited child of the daemon without blocking, whichever [`Processes`] tracks
+/// them, the children of a previous configuration or of in-process actions included
+pub fn wait_children() -> Vec<(u32, ExitStatus)> {
+    let mut exited = Vec::new();
+    loop {
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+        if pid <= 0 {
+            break;
+        }
+        exited.push((pid as u32, ExitStatus::from_raw(status)));
+    }
+    exited
+}
+
+/// [`wait_children`] for a single child, the tests run in parallel must not reap the children
+/// of each other
+#[cfg(test)]
+pub(crate) fn wait_child(pid: u32) -> Vec<(u32, ExitStatus)> {
+    let mut status = 0;
+    match unsafe { libc::waitpid(pid as libc::pid_t, &mut status, libc::WNOHANG) } {
+        res if res > 0 => vec![(pid, ExitStatus::from_raw(status))],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn reap() {
+        let mut processes = Processes::default();
+        let child = Command::new("false").spawn().unwrap();
+        let pid = child.id();
+        processes.insert(Process::new(0, Key::builder(), child));
+
+        let started = Instant::now();
+        let mut exited = Vec::new();
+        while exited.is_empty() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            exited = processes.reap(&mut wait_child(pid));
+        }
+        assert_eq!(exited.len(), 1);
+        assert_eq!(exited[0].0.id(), pid);
+        assert_eq!(exited[0].1.code(), Some(1));
+    }
+
+    #[test]
+    fn expire() {
+        let mut processes = Processes::default();
+        let child = Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id();
+        let mut process = Process::new(0, Key::builder(), child);
+        process.deadline = Some(Instant::now());
+        processes.insert(process);
+
+        processes.expire();
+        assert!(processes.deadline().unwrap() > Instant::now());
+        let started = Instant::now();
+        let mut exited = Vec::new();
+        while exited.is_empty() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            exited = processes.reap(&mut wait_child(pid));
+        }
+        assert_eq!(exited.len(), 1);
+        assert_eq!(exited[0].1.signal(), Some(libc::SIGTERM));
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    os::raw::{c_int, c_long, c_uchar},
+    ptr,
+    str::FromStr,
+    time::Duration,
+};
+
+use x11::xlib::{
+    AnyPropertyType, Atom, CurrentTime, Display, PropModeReplace, PropertyChangeMask,
+    PropertyNewValue, SelectionNotify, Success, Window, XChangeProperty, XConvertSelection,
+    XCreateSimpleWindow, XEvent, XFlush, XFree, XGetSelectionOwner, XGetWindowProperty,
+    XPropertyEvent, XSelectInput, XSelectionEvent, XSelectionRequestEvent, XSendEvent,
+    XSetSelectionOwner, XA_ATOM, XA_PRIMARY, XA_STRING,
+};
+
+use crate::keyboard::{intern, DisplayContext};
+
+/// How long to wait for the owner of a selection to convert it
+pub const CONVERT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Selection {
+    Primary,
+    Clipboard,
+}
+
+impl FromStr for Selection {
+    type Err = ();
+
+    fn from_str(selection: &str) -> Result<Self, Self::Err> {
+        match selection {
+            "primary" => Ok(Self::Primary),
+            "clipboard" => Ok(Self::Clipboard),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Selections owned by the daemon and the window used to own and receive them, each selection
+/// is converted to the property of the same name on the window
+#[derive(Debug)]
+pub(crate) struct Selections {
+    window: Window,
+    clipboard: Atom,
+    utf8: Atom,
+    targets: Atom,
+    incr: Atom,
+    owned: HashMap<Atom, String>,
+    /// content of the selections as last converted
+    converted: HashMap<Atom, String>,
+    /// incremental transfers in progress
+    incoming: HashMap<Atom, Vec<u8>>,
+}
+
+impl Selections {
+    pub(crate) unsafe fn new(display: *mut Display, root: Window) -> Self {
+        let window = XCreateSimpleWindow(display, root, 0, 0, 1, 1, 0, 0, 0);
+        // the chunks of the incremental transfers are announced by property changes
+        XSelectInput(display, window, PropertyChangeMask);
+        Self {
+            window,
+            clipboard: intern(display, "CLIPBOARD"),
+            utf8: intern(display, "UTF8_STRING"),
+            targets: intern(display, "TARGETS"),
+            incr: intern(display, "INCR"),
+            owned: HashMap::new(),
+            converted: HashMap::new(),
+            incoming: HashMap::new(),
+        }
+    }
+
+    fn atom(&self, selection: Selection) -> Atom {
+        match selection {
+            Selection::Primary => XA_PRIMARY,
+            Selection::Clipboard => self.clipboard,
+        }
+    }
+}
+
+/// Reading and owning the selections, the answers of the owners and the requests of the other
+/// clients are handled as they are decoded by [`crate::Keyboard::decode_event`]
+impl DisplayContext {
+    /// Content of a selection as UTF-8, owned by the daemon or as last converted by
+    /// [`DisplayContext::convert_selection`], `None` while the conversion is in progress
+    pub fn selection(&self, selection: Selection) -> Option<String> {
+        let atom = self.selections.atom(selection);
+        let selections = &self.selections;
+        selections
+            .owned
+            .get(&atom)
+            .or_else(|| selections.converted.get(&atom))
+            .cloned()
+    }
+
+    /// Whether the daemon owns the selection, its content is then known without converting it
+    pub fn owns_selection(&self, selection: Selection) -> bool {
+        let atom = self.selections.atom(selection);
+        self.selections.owned.contains_key(&atom)
+    }
+
+    /// Ask the owner of a selection to convert it to UTF-8 without waiting,
+    /// [`crate::Event::Selection`] is decoded once the content is available
+    pub fn convert_selection(&mut self, selection: Selection) {
+        let atom = self.selections.atom(selection);
+        let (window, utf8) = (self.selections.window, self.selections.utf8);
+        self.selections.converted.remove(&atom);
+        self.selections.incoming.remove(&atom);
+        let display = self.display_mut();
+        unsafe {
+            if XGetSelectionOwner(display, atom) == 0 {
+                self.selections.converted.insert(atom, String::new());
+                return;
+            }
+            XConvertSelection(display, atom, utf8, atom, window, CurrentTime);
+            XFlush(display);
+        }
+    }
+
+    /// Own a selection, its content is given to the clients asking for it until another
+    /// client takes it
+    pub fn set_selection(&mut self, selection: Selection, text: String) {
+        let atom = self.selections.atom(selection);
+        let window = self.selections.window;
+        self.selections.owned.insert(atom, text);
+        let display = self.display_mut();
+        unsafe {
+            XSetSelectionOwner(display, atom, window, CurrentTime);
+            XFlush(display);
+        }
+    }
+
+    /// Exchange the content of the primary selection and of the clipboard, as last converted
+    pub fn swap_selections(&mut self) {
+        let primary = self.selection(Selection::Primary).unwrap_or_default();
+        let clipboard = self.selection(Selection::Clipboard).unwrap_or_default();
+        self.set_selection(Selection::Primary, clipboard);
+        self.set_selection(Selection::Clipboard, primary);
+    }
+
+    /// Answer of the owner of a selection to [`DisplayContext::convert_selection`], returns
+    /// whether the content is complete
+    pub(crate) fn selection_notify(&mut self, event: &XSelectionEvent) -> bool {
+        if event.requestor != self.selections.window {
+            return false;
+        }
+        if event.property == 0 {
+            warn!("the selection can't be converted to text");
+            self.selections
+                .converted
+                .insert(event.selection, String::new());
+            return true;
+        }
+        match self.take_property(event.property) {
+            // taking the property started the transfer
+            Some((kind, _)) if kind == self.selections.incr => {
+                debug!("incremental selection transfer");
+                self.selections.incoming.insert(event.selection, Vec::new());
+                false
+            }
+            data => {
+                let text = data.map(|(_, bytes)| bytes).unwrap_or_default();
+                let text = String::from_utf8_lossy(&text).into_owned();
+                self.selections.converted.insert(event.selection, text);
+                true
+            }
+        }
+    }
+
+    /// Chunk of an incremental transfer, returns whether the content is complete
+    pub(crate) fn selection_chunk(&mut self, event: &XPropertyEvent) -> bool {
+        if event.window != self.selections.window
+            || event.state != PropertyNewValue
+            || !self.selections.incoming.contains_key(&event.atom)
+        {
+            return false;
+        }
+        let chunk = self.take_property(event.atom).map(|(_, bytes)| bytes);
+        match chunk {
+            // an empty chunk ends the transfer
+            Some(chunk) if !chunk.is_empty() => {
+                if let Some(bytes) = self.selections.incoming.get_mut(&event.atom) {
+                    bytes.extend_from_slice(&chunk);
+                }
+                false
+            }
+            _ => {
+                let bytes = self.selections.incoming.remove(&event.atom);
+                let text = String::from_utf8_lossy(&bytes.unwrap_or_default()).into_owned();
+                self.selections.converted.insert(event.atom, text);
+                true
+            }
+        }
+    }
+
+    /// Read and delete a property of the selection window, the bytes are only returned for the
+    /// 8 bits formats
+    fn take_property(&mut self, property: Atom) -> Option<(Atom, Vec<u8>)> {
+        let window = self.selections.window;
+        let (mut kind, mut format, mut items, mut after) = (0, 0, 0, 0);
+        let mut data: *mut c_uchar = ptr::null_mut();
+        let status = unsafe {
+            XGetWindowProperty(
+                self.display_mut(),
+                window,
+                property,
+                0,
+                c_long::from(i32::MAX) / 4,
+                i32::from(true),
+                AnyPropertyType as Atom,
+                &mut kind,
+                &mut format,
+                &mut items,
+                &mut after,
+                &mut data,
+            )
+        };
+        if status != i32::from(Success) || data.is_null() {
+            return None;
+        }
+        let bytes = match format {
+            8 => unsafe { std::slice::from_raw_parts(data, items as usize).to_vec() },
+            _ => Vec::new(),
+        };
+        unsafe { XFree(data.cast()) };
+        Some((kind, bytes))
+    }
+
+    /// Another client owns the selection now
+    pub(crate) fn clear_selection(&mut self, atom: Atom) {
+        self.selections.owned.remove(&atom);
+    }
+
+    /// Answer a client asking for a selection owned by the daemon
+    pub(crate) fn serve_selection(&mut self, request: &XSelectionRequestEvent) {
+        let Selections {
+            utf8,
+            targets,
+            ref owned,
+            ..
+        } = self.selections;
+        let text = owned
+            .get(&request.selection)
+            .map(|text| text.as_bytes().to_vec());
+        let display = self.display_mut();
+        let property = unsafe {
+            match text {
+                Some(_) if request.target == targets => {
+                    let supported = [targets, utf8, XA_STRING];
+                    change_property(display, request, XA_ATOM, 32, &supported);
+                    request.property
+                }
+                Some(ref text) if request.target == utf8 || request.target == XA_STRING => {
+                    change_property(display, request, request.target, 8, text);
+                    request.property
+                }
+                _ => 0,
+            }
+        };
+
+        let mut notify = XEvent::from(XSelectionEvent {
+            type_: SelectionNotify,
+            serial: 0,
+            send_event: i32::from(true),
+            display,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property,
+            time: request.time,
+        });
+        unsafe {
+            XSendEvent(display, request.requestor, i32::from(false), 0, &mut notify);
+            XFlush(display);
+        }
+    }
+}
+
+unsafe fn change_property<T>(
+    display: *mut Display,
+    request: &XSelectionRequestEvent,
+    kind: Atom,
+    format: c_int,
+    data: &[T],
+) {
+    XChangeProperty(
+        display,
+        request.requestor,
+        request.property,
+        kind,
+        format,
+        PropModeReplace,
+        data.as_ptr().cast(),
+        data.len() as c_int,
+    );
+}
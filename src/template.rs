@@ -0,0 +1,170 @@
+use std::{
+    fmt, io,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{exec::Context, selection::Selection};
+
+/// Command line whose placeholders are replaced each time it is run:
+/// - `{hotkey}`, `{keysym}` and `{mode}` of the binding
+/// - `{window_id}` and `{window_class}` of the focused window
+/// - `{display}` the hotkey fired on, e.g. `:0.1`
+/// - `{pointer_x}`, `{pointer_y}` and the Xinerama `{monitor}` under the pointer
+/// - `{timestamp}` in seconds since the epoch
+/// - `{clipboard}` and `{primary}` selections, converted before the command is built
+///
+/// Unknown placeholders are left as is.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Template(String);
+
+impl Template {
+    pub fn new(command: &str) -> Self {
+        Self(command.to_string())
+    }
+
+    /// Build the command, each placeholder is expanded in the argument it appears in. With
+    /// `shell`, the line is run by `sh -c` and the values are quoted instead.
+    pub fn command(&self, ctx: &mut Context, shell: bool) -> io::Result<Command> {
+        let mut lookup = |name: &str| placeholder(name, ctx);
+        let mut command = if shell {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(expand(&self.0, &mut lookup, quote)?);
+            command
+        } else {
+            let args = self.0.split(' ').filter(|arg| !arg.is_empty());
+            let args = args
+                .map(|arg| expand(arg, &mut lookup, str::to_string))
+                .collect::<io::Result<Vec<_>>>()?;
+            let (program, args) = args
+                .split_first()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        };
+        command.stdin(Stdio::null());
+        Ok(command)
+    }
+
+    /// Selections to convert before building the command
+    pub fn selections(&self) -> Vec<Selection> {
+        let placeholders = [
+            ("{primary}", Selection::Primary),
+            ("{clipboard}", Selection::Clipboard),
+        ];
+        placeholders
+            .iter()
+            .filter(|(placeholder, _)| self.0.contains(placeholder))
+            .map(|&(_, selection)| selection)
+            .collect()
+    }
+}
+
+impl fmt::Debug for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+/// Value of a placeholder, `None` for the unknown ones
+fn placeholder(name: &str, ctx: &mut Context) -> io::Result<Option<String>> {
+    let value = match name {
+        "hotkey" => ctx.key.to_string(),
+        "keysym" => ctx.key.sym_name(),
+        "mode" => ctx.mode.to_string(),
+        "window_id" => ctx.window.map(|w| w.id.to_string()).unwrap_or_default(),
+        "window_class" => ctx.window.map(|w| w.class.clone()).unwrap_or_default(),
+        "display" => ctx.display.screen_name(ctx.screen),
+        "pointer_x" => ctx.display.pointer().0.to_string(),
+        "pointer_y" => ctx.display.pointer().1.to_string(),
+        "monitor" => {
+            let (x, y) = ctx.display.pointer();
+            ctx.display.monitor_at(x, y).unwrap_or(0).to_string()
+        }
+        "timestamp" => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0)
+            .to_string(),
+        "clipboard" => ctx
+            .display
+            .selection(Selection::Clipboard)
+            .unwrap_or_default(),
+        "primary" => ctx
+            .display
+            .selection(Selection::Primary)
+            .unwrap_or_default(),
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Replace the `{name}` placeholders known to `lookup` by their escaped value
+fn expand<L, E>(template: &str, lookup: &mut L, escape: E) -> io::Result<String>
+where
+    L: FnMut(&str) -> io::Result<Option<String>>,
+    E: Fn(&str) -> String,
+{
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let (before, from) = rest.split_at(start);
+        expanded.push_str(before);
+        let value = match from.find('}') {
+            Some(end) => lookup(&from[1..end])?.map(|value| (value, end)),
+            None => None,
+        };
+        match value {
+            Some((value, end)) => {
+                expanded.push_str(&escape(&value));
+                rest = &from[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &from[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Single quote a value for `sh`
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> io::Result<Option<String>> {
+        Ok(match name {
+            "hotkey" => Some("super + a".to_string()),
+            "window_class" => Some("it's".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn placeholders() {
+        let expanded = expand("notify-send {hotkey}", &mut lookup, str::to_string).unwrap();
+        assert_eq!(expanded, "notify-send super + a");
+        let expanded = expand("{a,b} {hotkey", &mut lookup, str::to_string).unwrap();
+        assert_eq!(expanded, "{a,b} {hotkey");
+    }
+
+    #[test]
+    fn selections() {
+        let template = Template::new("xdg-open {clipboard}");
+        assert_eq!(template.selections(), vec![Selection::Clipboard]);
+        assert!(Template::new("echo {hotkey}").selections().is_empty());
+    }
+
+    #[test]
+    fn shell_quoting() {
+        let expanded = expand("echo {window_class}; ls", &mut lookup, quote).unwrap();
+        assert_eq!(expanded, r"echo 'it'\''s'; ls");
+    }
+}
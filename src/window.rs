@@ -0,0 +1,73 @@
+use x11::xlib::Window;
+
+/// Properties of the focused window used to select the window specific bindings
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WindowInfo {
+    pub id: Window,
+    /// Second part of `WM_CLASS`, e.g. `Alacritty`
+    pub class: String,
+    /// First part of `WM_CLASS`, e.g. `alacritty`
+    pub instance: String,
+    /// `_NET_WM_NAME` or `WM_NAME`
+    pub name: String,
+    /// `WM_WINDOW_ROLE`
+    pub role: String,
+}
+
+/// Condition on the focused window for a binding made in [`crate::Builder::window`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum WindowMatch {
+    /// `WM_CLASS` class is equal to
+    Class(String),
+    /// `WM_CLASS` instance is equal to
+    Instance(String),
+    /// Window title contains
+    Name(String),
+    /// `WM_WINDOW_ROLE` is equal to
+    Role(String),
+}
+
+impl WindowMatch {
+    pub fn class(class: &str) -> Self {
+        Self::Class(class.to_string())
+    }
+    pub fn instance(instance: &str) -> Self {
+        Self::Instance(instance.to_string())
+    }
+    pub fn name(name: &str) -> Self {
+        Self::Name(name.to_string())
+    }
+    pub fn role(role: &str) -> Self {
+        Self::Role(role.to_string())
+    }
+
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        match self {
+            Self::Class(class) => window.class == *class,
+            Self::Instance(instance) => window.instance == *instance,
+            Self::Name(name) => window.name.contains(name.as_str()),
+            Self::Role(role) => window.role == *role,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let window = WindowInfo {
+            id: 1,
+            class: "Alacritty".to_string(),
+            instance: "alacritty".to_string(),
+            name: "vim ~/notes.md".to_string(),
+            role: String::new(),
+        };
+        assert!(WindowMatch::class("Alacritty").matches(&window));
+        assert!(!WindowMatch::class("alacritty").matches(&window));
+        assert!(WindowMatch::instance("alacritty").matches(&window));
+        assert!(WindowMatch::name("vim").matches(&window));
+        assert!(!WindowMatch::role("browser").matches(&window));
+    }
+}
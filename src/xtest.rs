@@ -0,0 +1,277 @@
+use std::{
+    io, mem,
+    os::raw::{c_int, c_uint, c_ulong},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use x11::xlib::{
+    CurrentTime, Display, KeyCode, KeySym, XChangeKeyboardMapping, XDisplayKeycodes, XFlush, XFree,
+    XFreeModifiermap, XGetKeyboardMapping, XGetModifierMapping, XKeycodeToKeysym, XKeysymToKeycode,
+    XQueryKeymap, XSync, XUngrabKeyboard,
+};
+
+use crate::{
+    key::Key,
+    keyboard::{load_symbol, DisplayContext},
+};
+
+/// Pause after each key typed by [`DisplayContext::type_next`], and between remapping a keycode
+/// and typing it, for the clients to notice the new mapping
+pub const TYPE_DELAY: Duration = Duration::from_millis(12);
+
+type FakeKeyEvent = unsafe extern "C" fn(*mut Display, c_uint, c_int, c_ulong) -> c_int;
+
+/// `XTestFakeKeyEvent`, libXtst is loaded on first use so that it is only needed by the
+/// bindings synthesizing keys
+fn fake_key_event() -> io::Result<FakeKeyEvent> {
+    static FAKE_KEY_EVENT: OnceLock<Option<FakeKeyEvent>> = OnceLock::new();
+    let load = || {
+        let f = load_symbol("libXtst.so.6", "XTestFakeKeyEvent")?;
+        Some(unsafe { std::mem::transmute::<*mut libc::c_void, FakeKeyEvent>(f) })
+    };
+    FAKE_KEY_EVENT
+        .get_or_init(load)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "libXtst is not available"))
+}
+
+/// Text being typed in the focused window, a key at a time, see [`DisplayContext::type_text`]
+#[derive(Debug)]
+pub struct Typing {
+    chars: std::vec::IntoIter<char>,
+    /// keycode mapped to the characters missing from the layout
+    spare: Option<KeyCode>,
+    /// the spare keycode was mapped to the next character, it is typed at the next step
+    remapped: bool,
+    deadline: Instant,
+}
+
+impl Typing {
+    /// Time of the next step of [`DisplayContext::type_next`]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+/// Synthetic input through the XTest extension
+impl DisplayContext {
+    /// Type the hotkeys one after the other in the focused window. The modifiers held down are
+    /// released in the meantime so that they don't apply to the synthetic keys.
+    pub fn send_keys(&mut self, keys: &[Key]) -> io::Result<()> {
+        let codes = keys
+            .iter()
+            .map(|&key| self.key_codes(key))
+            .collect::<io::Result<Vec<_>>>()?;
+        self.synthesize(|display, fake| {
+            codes.iter().for_each(|codes| display.tap(fake, codes));
+            Ok(())
+        })
+    }
+
+    /// Start typing UTF-8 text in the focused window. The text is typed by calling
+    /// [`DisplayContext::type_next`] once [`Typing::deadline`] is reached, until it returns
+    /// `false`, so that the caller isn't blocked by the pauses between the keys.
+    pub fn type_text(&mut self, text: &str) -> io::Result<Typing> {
+        fake_key_event()?;
+        Ok(Typing {
+            chars: text.chars().collect::<Vec<_>>().into_iter(),
+            spare: None,
+            remapped: false,
+            deadline: Instant::now(),
+        })
+    }
+
+    /// Type the next character of `typing`, returns whether there are more steps. The
+    /// characters missing from the layout are typed by temporarily mapping them to an unused
+    /// keycode, in two steps.
+    pub fn type_next(&mut self, typing: &mut Typing) -> io::Result<bool> {
+        typing.deadline = Instant::now() + TYPE_DELAY;
+        if let (true, Some(code)) = (mem::take(&mut typing.remapped), typing.spare) {
+            self.synthesize(|display, fake| {
+                display.tap(fake, &[code]);
+                unsafe { XSync(display.display_mut(), 0) };
+                Ok(())
+            })?;
+            return Ok(true);
+        }
+        let sym = match typing.chars.next() {
+            Some(c) => char_keysym(c),
+            None => {
+                if let Some(code) = typing.spare.take() {
+                    self.remap(code, 0);
+                }
+                return Ok(false);
+            }
+        };
+        if let Ok(codes) = self.key_codes(Key::sym(sym)) {
+            self.synthesize(|display, fake| {
+                display.tap(fake, &codes);
+                Ok(())
+            })?;
+            return Ok(true);
+        }
+        let code = match typing.spare {
+            Some(code) => code,
+            None => *typing.spare.insert(self.spare_keycode()?),
+        };
+        self.remap(code, sym);
+        typing.remapped = true;
+        Ok(true)
+    }
+
+    /// Run `f` with the hotkey's grab and the held modifiers released
+    fn synthesize<F>(&mut self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Self, FakeKeyEvent) -> io::Result<()>,
+    {
+        let fake = fake_key_event()?;
+        let held = self.held_modifiers();
+        unsafe {
+            let display = self.display_mut();
+            // the hotkey's grab would get the keys otherwise
+            XUngrabKeyboard(display, CurrentTime);
+            held.iter().for_each(|&code| {
+                fake(display, code.into(), 0, CurrentTime);
+            });
+        }
+        let result = f(self, fake);
+        unsafe {
+            let display = self.display_mut();
+            held.iter().for_each(|&code| {
+                fake(display, code.into(), 1, CurrentTime);
+            });
+            XFlush(display);
+        }
+        result
+    }
+
+    /// Press the keycodes in order and release them in reverse
+    fn tap(&mut self, fake: FakeKeyEvent, codes: &[KeyCode]) {
+        let display = self.display_mut();
+        unsafe {
+            codes.iter().for_each(|&code| {
+                fake(display, code.into(), 1, CurrentTime);
+            });
+            codes.iter().rev().for_each(|&code| {
+                fake(display, code.into(), 0, CurrentTime);
+            });
+        }
+    }
+
+    /// Keycodes to press for a hotkey, its modifiers first
+    fn key_codes(&mut self, key: Key) -> io::Result<Vec<KeyCode>> {
+        let sym = key.sym;
+        let display = self.display_mut();
+        let code = unsafe { XKeysymToKeycode(display, sym) };
+        let level = (0..2).find(|&level| unsafe { XKeycodeToKeysym(display, code, level) } == sym);
+        let mut mask = key.mask;
+        match (code, level) {
+            (0, _) | (_, None) => {
+                let error = format!("no keycode for {}", key.sym_name());
+                return Err(io::Error::new(io::ErrorKind::NotFound, error));
+            }
+            // keysyms of the second level such as `A` or `exclam`
+            (_, Some(1)) => mask |= x11::xlib::ShiftMask,
+            _ => {}
+        }
+        let mut codes = self.modifier_codes(mask);
+        codes.push(code);
+        Ok(codes)
+    }
+
+    /// A keycode without keysyms
+    fn spare_keycode(&mut self) -> io::Result<KeyCode> {
+        let display = self.display_mut();
+        let (mut min, mut max, mut per_code) = (0, 0, 0);
+        unsafe {
+            XDisplayKeycodes(display, &mut min, &mut max);
+            let count = max - min + 1;
+            let syms = XGetKeyboardMapping(display, min as KeyCode, count, &mut per_code);
+            if syms.is_null() {
+                return Err(io::Error::other("unable to get the keyboard mapping"));
+            }
+            let per_code = per_code.max(1) as usize;
+            let mapping = std::slice::from_raw_parts(syms, count as usize * per_code);
+            let spare = mapping
+                .chunks(per_code)
+                .position(|syms| syms.iter().all(|&sym| sym == 0));
+            XFree(syms.cast());
+            spare
+                .map(|offset| (min as usize + offset) as KeyCode)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no spare keycode"))
+        }
+    }
+
+    /// Map the first two levels of a keycode to `sym`, `0` unmaps it
+    fn remap(&mut self, code: KeyCode, sym: KeySym) {
+        let mut syms = [sym, sym];
+        let display = self.display_mut();
+        unsafe {
+            XChangeKeyboardMapping(display, code.into(), 2, syms.as_mut_ptr(), 1);
+            XSync(display, 0);
+        }
+    }
+
+    /// First keycode of each modifier of `mask`
+    fn modifier_codes(&mut self, mask: u32) -> Vec<KeyCode> {
+        self.modifier_map()
+            .into_iter()
+            .enumerate()
+            .filter(|&(modifier, _)| mask & (1 << modifier) != 0)
+            .filter_map(|(_, codes)| codes.into_iter().find(|&code| code != 0))
+            .collect()
+    }
+
+    /// Modifier keys currently held down
+    fn held_modifiers(&mut self) -> Vec<KeyCode> {
+        let mut keymap = [0; 32];
+        unsafe { XQueryKeymap(self.display_mut(), keymap.as_mut_ptr()) };
+        let pressed = |code: KeyCode| keymap[usize::from(code / 8)] as u8 & (1 << (code % 8)) != 0;
+        self.modifier_map()
+            .into_iter()
+            .flatten()
+            .filter(|&code| code != 0 && pressed(code))
+            .collect()
+    }
+
+    /// Keycodes of the 8 modifiers
+    fn modifier_map(&mut self) -> Vec<Vec<KeyCode>> {
+        unsafe {
+            let map = XGetModifierMapping(self.display_mut());
+            if map.is_null() {
+                return Vec::new();
+            }
+            let per_modifier = (*map).max_keypermod as usize;
+            let codes = std::slice::from_raw_parts((*map).modifiermap, 8 * per_modifier);
+            let modifiers = codes
+                .chunks(per_modifier.max(1))
+                .map(<[_]>::to_vec)
+                .collect();
+            XFreeModifiermap(map);
+            modifiers
+        }
+    }
+}
+
+/// Keysym typing a character, the Unicode keysyms are used outside of Latin-1
+fn char_keysym(c: char) -> KeySym {
+    match c {
+        '\n' => KeySym::from(x11::keysym::XK_Return),
+        '\t' => KeySym::from(x11::keysym::XK_Tab),
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as KeySym,
+        c => 0x0100_0000 + c as KeySym,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keysyms() {
+        assert_eq!(char_keysym('a'), KeySym::from(x11::keysym::XK_a));
+        assert_eq!(char_keysym('é'), KeySym::from(x11::keysym::XK_eacute));
+        assert_eq!(char_keysym('\n'), KeySym::from(x11::keysym::XK_Return));
+        assert_eq!(char_keysym('€'), 0x0100_20ac);
+    }
+}